@@ -0,0 +1,129 @@
+use crate::jsonpath;
+use crate::multimap::{deserialize_scalar, MultiMap};
+use crate::Response;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(#[serde(deserialize_with = "deserialize_scalar")] String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn iter(&self) -> std::slice::Iter<'_, String> {
+        match self {
+            OneOrMany::One(s) => std::slice::from_ref(s).iter(),
+            OneOrMany::Many(v) => v.iter(),
+        }
+    }
+}
+
+/// Checks to run against a response, declared in the `assert:` section.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Assert {
+    status: Option<u32>,
+    header: Option<MultiMap>,
+    body: Option<OneOrMany>,
+    json: Option<HashMap<String, Value>>,
+    time: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssertResult {
+    pub pass: bool,
+    pub message: String,
+}
+
+impl AssertResult {
+    fn new(pass: bool, message: String) -> Self {
+        AssertResult { pass, message }
+    }
+}
+
+impl Assert {
+    pub fn check(&self, response: &Response) -> Vec<AssertResult> {
+        let mut v = Vec::new();
+
+        if let Some(status) = self.status {
+            v.push(AssertResult::new(
+                response.code == status,
+                format!("status == {} (actual {})", status, response.code),
+            ));
+        }
+
+        if let Some(header) = &self.header {
+            for (name, expected) in header {
+                let actual = response.header_value(name);
+                v.push(AssertResult::new(
+                    actual == Some(expected.trim()),
+                    format!(
+                        "header {} == {} (actual {})",
+                        name,
+                        expected,
+                        actual.unwrap_or("none")
+                    ),
+                ));
+            }
+        }
+
+        if let Some(body) = &self.body {
+            for s in body.iter() {
+                v.push(AssertResult::new(
+                    response.body.contains(s.as_str()),
+                    format!("body contains {}", s),
+                ));
+            }
+        }
+
+        if let Some(json) = &self.json {
+            let body: Option<Value> = serde_json::from_str(&response.body).ok();
+            for (path, expected) in json {
+                let actual = body.as_ref().and_then(|x| jsonpath::select(x, path));
+                v.push(AssertResult::new(
+                    actual == Some(expected),
+                    format!(
+                        "{} == {} (actual {})",
+                        path,
+                        expected,
+                        actual
+                            .map(|x| x.to_string())
+                            .unwrap_or_else(|| "none".into())
+                    ),
+                ));
+            }
+        }
+
+        if let Some(time) = self.time {
+            v.push(AssertResult::new(
+                response.time <= time,
+                format!("time <= {} ms (actual {} ms)", time, response.time),
+            ));
+        }
+
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_scalars() {
+        let assert: Assert =
+            serde_yaml::from_str("status: 200\nheader:\n  content-length: 5\n  x-ok: true")
+                .unwrap();
+        let response = Response {
+            body: "hello".into(),
+            header: "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Ok: true\r\n\r\n".into(),
+            code: 200,
+            time: 1,
+        };
+        let results = assert.check(&response);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|x| x.pass), "{:?}", results);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::multimap::deserialize_scalar;
+use crate::oauth2::Client;
+use crate::options::Options;
+use crate::request::Message;
+use crate::sigv4::Credentials;
+use curl::easy::{Auth as HttpAuth, Easy};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Authentication for a request, from the `auth:` section or the group default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// Disables the group default.
+    None,
+    Basic {
+        #[serde(deserialize_with = "deserialize_scalar")]
+        username: String,
+        #[serde(default, deserialize_with = "deserialize_scalar")]
+        password: String,
+    },
+    Bearer {
+        #[serde(deserialize_with = "deserialize_scalar")]
+        token: String,
+    },
+    Digest {
+        #[serde(deserialize_with = "deserialize_scalar")]
+        username: String,
+        #[serde(default, deserialize_with = "deserialize_scalar")]
+        password: String,
+    },
+    Ntlm {
+        #[serde(deserialize_with = "deserialize_scalar")]
+        username: String,
+        #[serde(default, deserialize_with = "deserialize_scalar")]
+        password: String,
+    },
+    /// Client credentials or password grant. The token is cached until it expires.
+    Oauth2(Client),
+    /// Signs the request with AWS Signature Version 4.
+    AwsSigv4(Credentials),
+}
+
+impl Auth {
+    /// Authenticates the request. `options` are the transport options of the request, which
+    /// are also used to fetch OAuth2 tokens.
+    pub fn apply(
+        &self,
+        easy: &mut Easy,
+        message: &mut Message,
+        options: &Options,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            Auth::None => Ok(()),
+            Auth::Basic { username, password } => {
+                login(easy, username, password, HttpAuth::new().basic(true))
+            }
+            Auth::Bearer { token } => bearer(message, token),
+            Auth::Digest { username, password } => {
+                login(easy, username, password, HttpAuth::new().digest(true))
+            }
+            Auth::Ntlm { username, password } => {
+                login(easy, username, password, HttpAuth::new().ntlm(true))
+            }
+            Auth::Oauth2(client) => bearer(message, &client.token(options)?),
+            Auth::AwsSigv4(credentials) => credentials.sign(message),
+        }
+    }
+}
+
+fn login(
+    easy: &mut Easy,
+    username: &str,
+    password: &str,
+    auth: &HttpAuth,
+) -> Result<(), Box<dyn Error>> {
+    easy.username(username)?;
+    easy.password(password)?;
+    Ok(easy.http_auth(auth)?)
+}
+
+fn bearer(message: &mut Message, token: &str) -> Result<(), Box<dyn Error>> {
+    message
+        .header
+        .push(("Authorization".into(), format!("Bearer {}", token)));
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use put::{config, Group, Request};
+use std::env::args;
+use std::io::{self, Read, Write};
+use std::{fs, process};
+
+const USAGE: &str = "usage: put-cli [-g <group id>] [--strict] [--no-cookies] [file]";
+
+const EXIT_USAGE: i32 = 1;
+const EXIT_PARSE: i32 = 2;
+const EXIT_TRANSPORT: i32 = 3;
+const EXIT_STATUS: i32 = 4;
+const EXIT_ASSERT: i32 = 5;
+
+struct Args {
+    group_id: Option<String>,
+    strict: bool,
+    no_cookies: bool,
+    file: Option<String>,
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| exit(EXIT_USAGE, e));
+
+    let mut group = args.group_id.as_ref().map(|id| {
+        find_group(id).unwrap_or_else(|| exit(EXIT_USAGE, format!("group not found: {}", id)))
+    });
+    if args.strict {
+        group.get_or_insert_with(Default::default).strict = Some(true);
+    }
+
+    let text = read_input(args.file.as_deref()).unwrap_or_else(|e| exit(EXIT_USAGE, e.to_string()));
+
+    let documents = Request::documents(&text);
+    if documents.is_empty() {
+        exit(EXIT_PARSE, "empty content");
+    }
+
+    let mut stdout = io::stdout();
+    let mut success = true;
+    let mut passed = true;
+    for (i, doc) in documents.into_iter().enumerate() {
+        let mut request = Request::parse(doc.to_string(), group.clone())
+            .unwrap_or_else(|e| exit(EXIT_PARSE, e.to_string()));
+        if args.no_cookies {
+            request.param.options.cookies = Some(false);
+        }
+        for warning in request.warnings() {
+            eprintln!("warning: {}", warning);
+        }
+        let response = request
+            .perform()
+            .unwrap_or_else(|e| exit(EXIT_TRANSPORT, e.to_string()));
+
+        if i > 0 {
+            let _ = stdout.write_all(b"\n");
+        }
+        let _ = stdout.write_all(response.header.as_bytes());
+        let _ = stdout.write_all(response.body.as_bytes());
+        let _ = stdout.flush();
+        eprintln!("{} {}: {} ms", request.method, request.url, response.time);
+
+        for error in request.graphql_errors(&response) {
+            eprintln!("  graphql error: {}", error);
+        }
+        for name in request.capture(&response) {
+            eprintln!("  capture {} not found", name);
+        }
+        for result in request.check(&response) {
+            eprintln!(
+                "  {} {}",
+                if result.pass { "ok" } else { "FAILED" },
+                result.message
+            );
+            passed = passed && result.pass;
+        }
+
+        success = success && (200..300).contains(&response.code);
+    }
+
+    if !passed {
+        process::exit(EXIT_ASSERT);
+    }
+    if !success {
+        process::exit(EXIT_STATUS);
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = args().skip(1);
+    let mut parsed = Args {
+        group_id: None,
+        strict: false,
+        no_cookies: false,
+        file: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-g" | "--group" => match args.next() {
+                Some(id) => parsed.group_id = Some(id),
+                None => return Err(USAGE.into()),
+            },
+            "--strict" => parsed.strict = true,
+            "--no-cookies" => parsed.no_cookies = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if parsed.file.is_none() => parsed.file = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    Ok(parsed)
+}
+
+fn find_group(id: &str) -> Option<Group> {
+    config::get_group_with_default()
+        .into_iter()
+        .find(|x| x.id == id)
+}
+
+fn read_input(file: Option<&str>) -> io::Result<String> {
+    match file {
+        Some(file) if file != "-" => fs::read_to_string(file),
+        _ => {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s)?;
+            Ok(s)
+        }
+    }
+}
+
+fn exit(code: i32, msg: impl AsRef<str>) -> ! {
+    eprintln!("{}", msg.as_ref());
+    process::exit(code)
+}
@@ -0,0 +1,114 @@
+use crate::{jsonpath, Response};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Json(String),
+    Header(String),
+    Regex(String),
+}
+
+/// Where to pull a variable from. A plain string is a JSONPath.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Capture {
+    Json(String),
+    Source(Source),
+}
+
+impl Capture {
+    pub fn validate(&self) -> Result<(), regex::Error> {
+        match self {
+            Capture::Source(Source::Regex(re)) => Regex::new(re).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn extract(&self, response: &Response) -> Option<String> {
+        match self {
+            Capture::Json(path) | Capture::Source(Source::Json(path)) => {
+                let body: Value = serde_json::from_str(&response.body).ok()?;
+                match jsonpath::select(&body, path)? {
+                    Value::String(s) => Some(s.clone()),
+                    value => Some(value.to_string()),
+                }
+            }
+            Capture::Source(Source::Header(name)) => {
+                response.header_value(name).map(|x| x.to_string())
+            }
+            Capture::Source(Source::Regex(re)) => {
+                let captures = Regex::new(re).ok()?.captures(&response.body)?;
+                let m = captures.get(1).or_else(|| captures.get(0))?;
+                Some(m.as_str().to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn response() -> Response {
+        Response {
+            body: r#"{"data": {"token": "abc", "id": 7}, "html": "csrf=\"f00\""}"#.into(),
+            header: "HTTP/1.1 302 Found\r\nX-Session: old\r\n\r\n\
+                     HTTP/1.1 200 OK\r\nX-Session: s1\r\n\r\n"
+                .into(),
+            code: 200,
+            time: 1,
+        }
+    }
+
+    fn parse(yaml: &str) -> BTreeMap<String, Capture> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn untagged() {
+        let captures =
+            parse("a: $.data.token\nb:\n  header: X-Session\nc:\n  regex: 'id\": (\\d+)'");
+        assert!(matches!(captures["a"], Capture::Json(ref x) if x == "$.data.token"));
+        assert!(
+            matches!(captures["b"], Capture::Source(Source::Header(ref x)) if x == "X-Session")
+        );
+        assert!(matches!(captures["c"], Capture::Source(Source::Regex(_))));
+    }
+
+    #[test]
+    fn extract() {
+        let response = response();
+        let captures = parse(
+            "token: $.data.token\n\
+             id:\n  json: $.data.id\n\
+             data: $.data\n\
+             missing: $.nope\n\
+             session:\n  header: x-session\n\
+             csrf:\n  regex: 'csrf=\\\\\"(\\w+)'\n\
+             whole:\n  regex: '\\d+'\n\
+             none:\n  regex: 'zzz'",
+        );
+        let extract = |name: &str| captures[name].extract(&response);
+        assert_eq!(extract("token").as_deref(), Some("abc"));
+        assert_eq!(extract("id").as_deref(), Some("7"));
+        assert_eq!(
+            extract("data").as_deref(),
+            Some(r#"{"id":7,"token":"abc"}"#)
+        );
+        assert_eq!(extract("missing"), None);
+        assert_eq!(extract("session").as_deref(), Some("s1"));
+        assert_eq!(extract("csrf").as_deref(), Some("f00"));
+        assert_eq!(extract("whole").as_deref(), Some("7"));
+        assert_eq!(extract("none"), None);
+    }
+
+    #[test]
+    fn validate() {
+        assert!(parse("a:\n  regex: '(\\w+'")["a"].validate().is_err());
+        assert!(parse("a:\n  regex: '(\\w+)'")["a"].validate().is_ok());
+    }
+}
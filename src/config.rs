@@ -1,14 +1,55 @@
-use crate::app_dir;
+use crate::auth::Auth;
+use crate::options::Options;
+use crate::sign::Signature;
+use crate::{app_dir, try_app_dir};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{collections::HashMap, fs, io, path::PathBuf};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Group {
     pub id: String,
     pub name: String,
     pub base_url: Option<String>,
     pub env: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dotenv: Option<String>,
+    /// Makes unresolved variables a parse error instead of leaving them in the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+    /// Directory that relative file paths in requests are resolved against. A relative
+    /// directory is relative to the config dir, like `dotenv`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_dir: Option<String>,
+    /// Default transport options for the group's requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Options>,
+    /// Default authentication, used by requests without an `auth:` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    /// Default signature header, used by requests without a `sign:` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sign: Option<Signature>,
+}
+
+impl Group {
+    /// Reads the group's dotenv file. Relative paths are resolved against the app dir.
+    pub fn load_dotenv(&self) -> io::Result<HashMap<String, String>> {
+        match &self.dotenv {
+            Some(path) => fs::read_to_string(app_dir().join(path))
+                .map(|s| parse_dotenv(&s))
+                .map_err(|e| io::Error::new(e.kind(), format!("dotenv {}: {}", path, e))),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Returns `base_dir`, resolved against the config dir.
+    pub fn resolved_base_dir(&self) -> Option<String> {
+        self.base_dir
+            .as_ref()
+            .map(|dir| app_dir().join(dir).display().to_string())
+    }
 }
 
 static mut STATE: Option<State> = None;
@@ -54,8 +95,7 @@ pub fn get_group_with_default() -> Vec<Group> {
     let default = Group {
         id: "".to_string(),
         name: "默认分组".to_string(),
-        base_url: None,
-        env: None,
+        ..Default::default()
     };
 
     match get_group() {
@@ -97,6 +137,75 @@ pub fn save_group(s: &str) -> std::io::Result<()> {
     fs::write(group_path(), s)
 }
 
+pub fn read_cookie(group_id: &str) -> String {
+    cookie_path(group_id)
+        .and_then(fs::read_to_string)
+        .unwrap_or_default()
+}
+
+pub fn save_cookie(group_id: &str, s: &str) -> io::Result<()> {
+    fs::write(cookie_path(group_id)?, s)
+}
+
+pub fn clear_cookie(group_id: &str) -> io::Result<()> {
+    match fs::remove_file(cookie_path(group_id)?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the cookie jar of a group, a Netscape cookie file that curl reads and writes.
+pub fn cookie_path(group_id: &str) -> io::Result<PathBuf> {
+    let mut file = try_app_dir()?;
+    file.push("cookies");
+    fs::create_dir_all(&file)?;
+    file.push(cookie_file_name(group_id));
+    Ok(file)
+}
+
+/// Percent-encodes everything but letters and digits, so that different ids never share a
+/// file. `_` is always encoded in ids, which leaves `_default` for the default group.
+fn cookie_file_name(group_id: &str) -> String {
+    if group_id.is_empty() {
+        "_default.txt".into()
+    } else {
+        format!("{}.txt", utf8_percent_encode(group_id, NON_ALPHANUMERIC))
+    }
+}
+
+fn parse_dotenv(s: &str) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for line in s.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = match line.strip_prefix("export ") {
+            Some(line) => line.trim_start(),
+            None => line,
+        };
+        let pos = match line.find('=') {
+            Some(pos) => pos,
+            None => continue,
+        };
+
+        let key = line[..pos].trim();
+        let value = line[pos + 1..].trim();
+        let value = match value.chars().next() {
+            Some(q @ '"') | Some(q @ '\'') if value.len() > 1 && value.ends_with(q) => {
+                &value[1..value.len() - 1]
+            }
+            _ => match value.find(" #") {
+                Some(i) => value[..i].trim_end(),
+                None => value,
+            },
+        };
+        map.insert(key.to_string(), value.to_string());
+    }
+    map
+}
+
 fn group_path() -> PathBuf {
     let mut file = app_dir();
     file.push("group.json");
@@ -114,3 +223,61 @@ fn state_path() -> PathBuf {
     file.push("state.json");
     file
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotenv() {
+        let map = parse_dotenv(
+            "# comment\n\
+             A=1\n\
+             export B = two words \n\
+             C=\"quoted # not a comment\"\n\
+             D='x=y'\n\
+             E=value # comment\n\
+             invalid line\n\
+             F=\n",
+        );
+        assert_eq!(map.len(), 6);
+        assert_eq!(map["A"], "1");
+        assert_eq!(map["B"], "two words");
+        assert_eq!(map["C"], "quoted # not a comment");
+        assert_eq!(map["D"], "x=y");
+        assert_eq!(map["E"], "value");
+        assert_eq!(map["F"], "");
+    }
+
+    #[test]
+    fn dotenv_error() {
+        let group = Group {
+            dotenv: Some("/nonexistent/.env".into()),
+            ..Default::default()
+        };
+        let err = group.load_dotenv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with("dotenv /nonexistent/.env: "));
+    }
+
+    #[test]
+    fn cookie_file_names() {
+        let ids = [
+            "", "default", "_default", "a.b", "a_b", "a/b", "a%2Eb", "分组",
+        ];
+        let names: Vec<_> = ids.iter().map(|x| cookie_file_name(x)).collect();
+        assert_eq!(
+            names,
+            [
+                "_default.txt",
+                "default.txt",
+                "%5Fdefault.txt",
+                "a%2Eb.txt",
+                "a%5Fb.txt",
+                "a%2Fb.txt",
+                "a%252Eb.txt",
+                "%E5%88%86%E7%BB%84.txt"
+            ]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::config;
+use crate::dialog::Dialog;
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Edits the cookie jar of a group.
+#[derive(Clone)]
+pub struct CookieDialog {
+    dialog: Rc<Dialog>,
+    group_id: Rc<String>,
+}
+
+impl CookieDialog {
+    pub fn new(group_id: String) -> Self {
+        let text = config::read_cookie(&group_id);
+        let dlg = CookieDialog {
+            dialog: Rc::new(Dialog::new(Cow::Owned(text), "")),
+            group_id: Rc::new(group_id),
+        };
+        let this = dlg.clone();
+        dlg.on_save(move |text| this.save(text));
+        dlg.setup();
+        dlg
+    }
+
+    fn save(&self, text: &str) -> bool {
+        let result = if text.trim().is_empty() {
+            config::clear_cookie(&self.group_id)
+        } else {
+            config::save_cookie(&self.group_id, text)
+        };
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                self.show_error(e.to_string());
+                false
+            }
+        }
+    }
+}
+
+impl Deref for CookieDialog {
+    type Target = Rc<Dialog>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dialog
+    }
+}
@@ -13,6 +13,8 @@ pub trait Editor {
     fn text(&self) -> Option<GString>;
 
     fn set_text(&self, text: &str) -> Option<()>;
+
+    fn cursor_line(&self) -> Option<usize>;
 }
 
 impl Editor for View {
@@ -45,4 +47,10 @@ impl Editor for View {
         buffer.set_text(text);
         Some(())
     }
+
+    fn cursor_line(&self) -> Option<usize> {
+        let buffer = self.get_buffer()?;
+        let iter = buffer.get_iter_at_mark(&buffer.get_insert()?);
+        Some(iter.get_line() as usize)
+    }
 }
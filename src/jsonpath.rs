@@ -0,0 +1,67 @@
+use serde_json::Value;
+
+/// Selects a value with a simple JSONPath expression such as `$.data.items[0]['name']`.
+pub fn select<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim();
+    let mut s = path.strip_prefix('$').unwrap_or(path);
+    let mut value = value;
+
+    while !s.is_empty() {
+        if s.starts_with('[') {
+            let end = s.find(']')?;
+            let key = s[1..end].trim();
+            value = if key.starts_with('\'') || key.starts_with('"') {
+                value.get(key.get(1..key.len() - 1)?)?
+            } else {
+                index(value, key.parse().ok()?)?
+            };
+            s = &s[end + 1..];
+        } else {
+            if s.starts_with('.') {
+                s = &s[1..];
+            }
+            let end = s.find(&['.', '['][..]).unwrap_or(s.len());
+            value = value.get(&s[..end])?;
+            s = &s[end..];
+        }
+    }
+
+    Some(value)
+}
+
+fn index(value: &Value, i: isize) -> Option<&Value> {
+    let array = value.as_array()?;
+    if i < 0 {
+        array.get(array.len().checked_sub(i.unsigned_abs())?)
+    } else {
+        array.get(i as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn select_paths() {
+        let value = json!({"data": {"items": [{"name": "a"}, {"name": "b"}], "a.b": 1}});
+        assert_eq!(select(&value, "$.data.items[0].name"), Some(&json!("a")));
+        assert_eq!(select(&value, "data.items[1]['name']"), Some(&json!("b")));
+        assert_eq!(select(&value, "$.data[\"a.b\"]"), Some(&json!(1)));
+        assert_eq!(select(&value, "$.data.items[-1].name"), Some(&json!("b")));
+        assert_eq!(select(&value, "$"), Some(&value));
+        assert_eq!(select(&value, "$.data.items[2]"), None);
+        assert_eq!(select(&value, "$.data.items[-3]"), None);
+        assert_eq!(select(&value, "$.data.missing"), None);
+        assert_eq!(select(&value, "$.data.items[x]"), None);
+        assert_eq!(select(&value, "$.data.items[0"), None);
+    }
+
+    #[test]
+    fn select_min_index() {
+        let value = json!([1, 2]);
+        let path = format!("$[{}]", isize::MIN);
+        assert_eq!(select(&value, &path), None);
+    }
+}
@@ -1,29 +1,63 @@
+mod assert;
+mod auth;
+mod capture;
 pub mod config;
+#[cfg(feature = "gui")]
+mod cookie_dialog;
+#[cfg(feature = "gui")]
 mod css_dialog;
 pub mod db;
+#[cfg(feature = "gui")]
 mod dialog;
+#[cfg(feature = "gui")]
 mod editor;
+#[cfg(feature = "gui")]
 mod group_dialog;
+mod jsonpath;
+mod multimap;
+mod oauth2;
+mod options;
+#[cfg(any(feature = "gui", test))]
+mod pretty;
 mod request;
+#[cfg(feature = "gui")]
 mod request_store;
+mod sign;
+mod sigv4;
+#[cfg(test)]
+mod test_server;
+mod variable;
+#[cfg(feature = "gui")]
 mod window;
+pub use assert::AssertResult;
 pub use config::Group;
+#[cfg(feature = "gui")]
+pub use cookie_dialog::CookieDialog;
+#[cfg(feature = "gui")]
 pub use css_dialog::CssDialog;
+#[cfg(feature = "gui")]
 pub use editor::Editor;
+#[cfg(feature = "gui")]
 pub use group_dialog::GroupDialog;
 pub use request::Request;
 pub use request::Response;
+#[cfg(feature = "gui")]
 pub use request_store::RequestStore;
+#[cfg(feature = "gui")]
 pub use window::Window;
 
-use std::fs;
 use std::path::PathBuf;
+use std::{fs, io};
 
 pub fn app_dir() -> PathBuf {
-    let mut dir = dirs::config_dir().expect("get config dir failed");
+    try_app_dir().expect("create config dir failed")
+}
+
+/// Returns the config dir of the app, creating it if it doesn't exist.
+pub(crate) fn try_app_dir() -> io::Result<PathBuf> {
+    let mut dir = dirs::config_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "config dir not found"))?;
     dir.push("put");
-    if !dir.exists() {
-        fs::create_dir(dir.as_path()).expect("create dir failed");
-    }
-    dir
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
 }
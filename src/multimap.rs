@@ -0,0 +1,251 @@
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::fmt::{self, Formatter};
+use std::marker::PhantomData;
+
+/// An ordered map that allows repeated keys. Values may be any YAML scalar or a list of
+/// scalars, which adds one entry per element.
+#[derive(Debug, Clone, Default)]
+pub struct MultiMap(Vec<(String, String)>);
+
+impl MultiMap {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl From<Vec<(String, String)>> for MultiMap {
+    fn from(v: Vec<(String, String)>) -> Self {
+        MultiMap(v)
+    }
+}
+
+impl<'a> IntoIterator for &'a MultiMap {
+    type Item = &'a (String, String);
+    type IntoIter = std::slice::Iter<'a, (String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'de> Deserialize<'de> for MultiMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor;
+
+        impl<'de> Visitor<'de> for MapVisitor {
+            type Value = MultiMap;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_unit<E>(self) -> Result<MultiMap, E> {
+                Ok(MultiMap::default())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<MultiMap, A::Error> {
+                let mut v = Vec::new();
+                while let Some((key, values)) = map.next_entry::<Scalar, Values>()? {
+                    for value in values.0 {
+                        v.push((key.0.clone(), value));
+                    }
+                }
+                Ok(MultiMap(v))
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor)
+    }
+}
+
+/// Map entries in document order, for sections whose values are not all scalars.
+#[derive(Debug)]
+pub(crate) struct Entries<V>(pub Vec<(String, V)>);
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Entries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+            type Value = Entries<V>;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_unit<E>(self) -> Result<Entries<V>, E> {
+                Ok(Entries(Vec::new()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Entries<V>, A::Error> {
+                let mut v = Vec::new();
+                while let Some((key, value)) = map.next_entry::<Scalar, V>()? {
+                    v.push((key.0, value));
+                }
+                Ok(Entries(v))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
+
+/// Deserializes a string, number, boolean or null as a string.
+pub(crate) fn deserialize_scalar<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    deserializer.deserialize_any(ScalarVisitor)
+}
+
+/// Like `deserialize_scalar`, for optional fields, which also need `#[serde(default)]`.
+pub(crate) fn deserialize_optional_scalar<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    deserialize_scalar(deserializer).map(Some)
+}
+
+struct Scalar(String);
+
+struct ScalarVisitor;
+
+impl<'de> Visitor<'de> for ScalarVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a string, number or boolean")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<String, E> {
+        Ok(v)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_unit<E>(self) -> Result<String, E> {
+        Ok(String::new())
+    }
+}
+
+impl<'de> Deserialize<'de> for Scalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ScalarVisitor).map(Scalar)
+    }
+}
+
+/// A scalar or a list of scalars.
+struct Values(Vec<String>);
+
+impl<'de> Deserialize<'de> for Values {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValuesVisitor;
+
+        impl<'de> Visitor<'de> for ValuesVisitor {
+            type Value = Values;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a scalar or a list of scalars")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Values, E> {
+                Ok(Values(vec![v.to_string()]))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Values, E> {
+                Ok(Values(vec![v]))
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Values, E> {
+                Ok(Values(vec![v.to_string()]))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Values, E> {
+                Ok(Values(vec![v.to_string()]))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Values, E> {
+                Ok(Values(vec![v.to_string()]))
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Values, E> {
+                Ok(Values(vec![v.to_string()]))
+            }
+
+            fn visit_unit<E>(self) -> Result<Values, E> {
+                Ok(Values(vec![String::new()]))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Values, A::Error> {
+                let mut v = Vec::new();
+                while let Some(s) = seq.next_element::<Scalar>()? {
+                    v.push(s.0);
+                }
+                Ok(Values(v))
+            }
+        }
+
+        deserializer.deserialize_any(ValuesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multimap() {
+        let map: MultiMap = serde_yaml::from_str("b: 1\na: [x, true, 2.5]\nc:\nd: ''").unwrap();
+        let entries: Vec<_> = map
+            .into_iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("b", "1"),
+                ("a", "x"),
+                ("a", "true"),
+                ("a", "2.5"),
+                ("c", ""),
+                ("d", "")
+            ]
+        );
+        assert_eq!(map.get("a"), Some("x"));
+        assert_eq!(map.get("e"), None);
+        assert!(serde_yaml::from_str::<MultiMap>("a: {b: 1}").is_err());
+    }
+
+    #[test]
+    fn entries() {
+        #[derive(serde::Deserialize)]
+        struct Text(#[serde(deserialize_with = "deserialize_scalar")] String);
+
+        let Entries(entries) =
+            serde_yaml::from_str::<Entries<Text>>("z: 1\na: b\nm: false").unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.0.as_str()))
+            .collect();
+        assert_eq!(entries, [("z", "1"), ("a", "b"), ("m", "false")]);
+    }
+}
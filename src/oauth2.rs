@@ -0,0 +1,344 @@
+use crate::multimap::{deserialize_optional_scalar, deserialize_scalar, MultiMap};
+use crate::options::Options;
+use crate::request::http_build_query;
+use curl::easy::Easy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Tokens are refreshed this long before they expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Access tokens, keyed by token URL, client, scopes, user and secrets.
+static TOKENS: Mutex<Option<HashMap<String, Token>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Grant {
+    #[default]
+    ClientCredentials,
+    Password,
+}
+
+/// An OAuth2 client, from an `auth:` section with `type: oauth2`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Client {
+    #[serde(default)]
+    pub grant: Grant,
+    pub token_url: String,
+    #[serde(deserialize_with = "deserialize_scalar")]
+    pub client_id: String,
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    pub client_secret: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// For the password grant.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub username: Option<String>,
+    /// For the password grant.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+impl Client {
+    /// Returns a cached access token, fetching a new one if there is none or it has expired.
+    /// The token request is sent with the timeouts, proxy and TLS settings in `options`.
+    pub fn token(&self, options: &Options) -> Result<String, Box<dyn Error>> {
+        let key = self.key();
+        let cached = TOKENS
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|x| x.get(&key))
+            .cloned();
+
+        let token = match cached {
+            Some(token) if !token.expired() => return Ok(token.access_token),
+            Some(Token {
+                refresh_token: Some(refresh_token),
+                ..
+            }) => self
+                .refresh(options, &refresh_token)
+                .or_else(|_| self.fetch(options))?,
+            _ => self.fetch(options)?,
+        };
+
+        let access_token = token.access_token.clone();
+        TOKENS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(key, token);
+        Ok(access_token)
+    }
+
+    /// Drops the cached token, so that the next request fetches a new one.
+    pub fn invalidate(&self) {
+        if let Some(tokens) = TOKENS.lock().unwrap().as_mut() {
+            tokens.remove(&self.key());
+        }
+    }
+
+    /// The cache key. Secrets are hashed, so that changing them fetches a new token.
+    fn key(&self) -> String {
+        let secrets = format!(
+            "{}\n{}",
+            self.client_secret,
+            self.password.as_deref().unwrap_or_default()
+        );
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.token_url,
+            self.client_id,
+            self.scopes.join(" "),
+            self.username.as_deref().unwrap_or_default(),
+            hex::encode(Sha256::digest(secrets.as_bytes()))
+        )
+    }
+
+    fn fetch(&self, options: &Options) -> Result<Token, Box<dyn Error>> {
+        let mut form = vec![];
+        match self.grant {
+            Grant::ClientCredentials => {
+                form.push(("grant_type".into(), "client_credentials".into()));
+            }
+            Grant::Password => {
+                let username = self
+                    .username
+                    .clone()
+                    .ok_or("oauth2: password grant requires username")?;
+                form.push(("grant_type".into(), "password".into()));
+                form.push(("username".into(), username));
+                form.push(("password".into(), self.password.clone().unwrap_or_default()));
+            }
+        }
+        if !self.scopes.is_empty() {
+            form.push(("scope".into(), self.scopes.join(" ")));
+        }
+        self.request(options, form)
+    }
+
+    fn refresh(&self, options: &Options, refresh_token: &str) -> Result<Token, Box<dyn Error>> {
+        self.request(
+            options,
+            vec![
+                ("grant_type".into(), "refresh_token".into()),
+                ("refresh_token".into(), refresh_token.into()),
+            ],
+        )
+    }
+
+    fn request(
+        &self,
+        options: &Options,
+        form: Vec<(String, String)>,
+    ) -> Result<Token, Box<dyn Error>> {
+        let mut easy = Easy::new();
+        // the socket is where the API is served, not necessarily the token endpoint
+        Options {
+            unix_socket: None,
+            ..options.clone()
+        }
+        .apply(&mut easy)?;
+        easy.url(&self.token_url)?;
+        easy.post_fields_copy(http_build_query(&MultiMap::from(form)).as_bytes())?;
+        easy.username(&self.client_id)?;
+        easy.password(&self.client_secret)?;
+
+        let mut body = Vec::new();
+        {
+            let mut transfer = easy.transfer();
+            transfer.write_function(|bytes| {
+                body.extend_from_slice(bytes);
+                Ok(bytes.len())
+            })?;
+            transfer.perform()?;
+        }
+
+        let body = String::from_utf8_lossy(&body);
+        let code = easy.response_code()?;
+        if !(200..300).contains(&code) {
+            return Err(format!("oauth2 token request failed: {} {}", code, error(&body)).into());
+        }
+
+        let res: TokenResponse =
+            serde_json::from_str(&body).map_err(|e| format!("oauth2 token response: {}", e))?;
+        Ok(Token {
+            access_token: res.access_token,
+            refresh_token: res.refresh_token,
+            expires_at: res
+                .expires_in
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+        })
+    }
+}
+
+impl Token {
+    fn expired(&self) -> bool {
+        match self.expires_at {
+            Some(at) => Instant::now() + EXPIRY_MARGIN >= at,
+            None => false,
+        }
+    }
+}
+
+/// Returns the `error_description` or `error` of an error response, or the body.
+fn error(body: &str) -> String {
+    let value: serde_json::Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(_) => return body.trim().to_string(),
+    };
+    value
+        .get("error_description")
+        .or_else(|| value.get("error"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::Server;
+    use crate::Request;
+
+    fn client(server: &Server) -> Client {
+        Client {
+            grant: Grant::ClientCredentials,
+            token_url: format!("{}/token", server.url),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scopes: vec!["read".into(), "write".into()],
+            username: None,
+            password: None,
+        }
+    }
+
+    fn token(access_token: &str, extra: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\nContent-Type: application/json\n\n\
+             {{\"access_token\": \"{}\"{}}}",
+            access_token, extra
+        )
+    }
+
+    #[test]
+    fn fetch_and_cache() {
+        let server = Server::start(vec![&token("a", ", \"expires_in\": 3600")]);
+        let client = client(&server);
+        assert_eq!(client.token(&Options::default()).unwrap(), "a");
+        assert_eq!(client.token(&Options::default()).unwrap(), "a");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        // `id:secret`
+        assert!(requests[0].contains("Authorization: Basic aWQ6c2VjcmV0\r\n"));
+        assert!(requests[0].ends_with("grant%5Ftype=client%5Fcredentials&scope=read%20write"));
+    }
+
+    #[test]
+    fn password_grant() {
+        let server = Server::start(vec![&token("a", ""), &token("b", "")]);
+        let mut client = Client {
+            grant: Grant::Password,
+            username: Some("user".into()),
+            password: Some("p&ss".into()),
+            scopes: vec![],
+            ..client(&server)
+        };
+        assert_eq!(client.token(&Options::default()).unwrap(), "a");
+        // a changed password is not served the cached token
+        client.password = Some("other".into());
+        assert_eq!(client.token(&Options::default()).unwrap(), "b");
+        assert!(
+            server.requests()[0].ends_with("grant%5Ftype=password&username=user&password=p%26ss")
+        );
+    }
+
+    #[test]
+    fn refresh() {
+        // expired at once, because it is within the expiry margin
+        let expired = ", \"expires_in\": 0, \"refresh_token\": \"r\"";
+        let server = Server::start(vec![&token("a", expired), &token("b", "")]);
+        let client = client(&server);
+        assert_eq!(client.token(&Options::default()).unwrap(), "a");
+        assert_eq!(client.token(&Options::default()).unwrap(), "b");
+        assert!(server.requests()[1].ends_with("grant%5Ftype=refresh%5Ftoken&refresh%5Ftoken=r"));
+    }
+
+    #[test]
+    fn fetch_when_refresh_fails() {
+        let expired = ", \"expires_in\": 0, \"refresh_token\": \"r\"";
+        let server = Server::start(vec![
+            &token("a", expired),
+            "HTTP/1.1 400 Bad Request\n\n{\"error\": \"invalid_grant\"}",
+            &token("c", ""),
+        ]);
+        let client = client(&server);
+        assert_eq!(client.token(&Options::default()).unwrap(), "a");
+        assert_eq!(client.token(&Options::default()).unwrap(), "c");
+        let requests = server.requests();
+        assert!(requests[1].contains("grant%5Ftype=refresh%5Ftoken"));
+        assert!(requests[2].contains("grant%5Ftype=client%5Fcredentials"));
+    }
+
+    #[test]
+    fn error_response() {
+        let server = Server::start(vec![
+            "HTTP/1.1 401 Unauthorized\n\n{\"error\": \"invalid_client\", \"error_description\": \"bad secret\"}",
+        ]);
+        let e = client(&server).token(&Options::default()).unwrap_err();
+        assert_eq!(e.to_string(), "oauth2 token request failed: 401 bad secret");
+    }
+
+    #[test]
+    fn invalidate_on_401() {
+        let server = Server::start(vec![
+            &token("a", ""),
+            "HTTP/1.1 401 Unauthorized",
+            &token("b", ""),
+            "HTTP/1.1 200 OK",
+        ]);
+        let yaml = format!(
+            "GET {0}/api\n\
+             options:\n  cookies: false\n\
+             auth:\n  type: oauth2\n  token_url: {0}/token\n  client_id: id",
+            server.url
+        );
+        let request = Request::parse(yaml, None).unwrap();
+        assert_eq!(request.perform().unwrap().code, 401);
+        assert_eq!(request.perform().unwrap().code, 200);
+
+        let requests = server.requests();
+        assert!(requests[1].contains("Authorization: Bearer a\r\n"));
+        assert!(requests[2].starts_with("POST /token "));
+        assert!(requests[3].contains("Authorization: Bearer b\r\n"));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::request::resolve_path;
+use curl::easy::{Easy, HttpVersion, IpResolve, List};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+
+/// Transport options, from the `options:` section of a request or the group defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Options {
+    /// Seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    /// Seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_redirects: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_tls: Option<bool>,
+    /// `1.0`, `1.1`, `2` or `2-prior-knowledge`
+    #[serde(
+        default,
+        deserialize_with = "deserialize_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub http_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed: Option<bool>,
+    /// Client certificate file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert: Option<String>,
+    /// `PEM` (default), `DER` or `P12`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_type: Option<String>,
+    /// Private key file, if it isn't in `cert`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_password: Option<String>,
+    /// CA bundle used to verify the server instead of the system one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+    /// `http://`, `https://`, `socks5://` or `socks5h://` URL, or `false` to connect directly
+    #[serde(
+        default,
+        deserialize_with = "deserialize_proxy",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_password: Option<String>,
+    /// Hosts that are not reached through the proxy
+    #[serde(
+        default,
+        deserialize_with = "deserialize_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub no_proxy: Option<Vec<String>>,
+    /// Unix domain socket to connect to instead of the host in the URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<String>,
+    /// `host:port` to the address to connect to, keeping the Host header and SNI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve: Option<BTreeMap<String, String>>,
+    /// `4`, `6` or `any`
+    #[serde(
+        default,
+        deserialize_with = "deserialize_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ip_version: Option<String>,
+    /// Keeps cookies in the group's cookie jar between requests, `true` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookies: Option<bool>,
+}
+
+impl Options {
+    /// Fills the options that are not set from `default`.
+    pub fn merge(self, default: &Options) -> Options {
+        Options {
+            timeout: self.timeout.or(default.timeout),
+            connect_timeout: self.connect_timeout.or(default.connect_timeout),
+            follow_redirects: self.follow_redirects.or(default.follow_redirects),
+            max_redirects: self.max_redirects.or(default.max_redirects),
+            verify_tls: self.verify_tls.or(default.verify_tls),
+            http_version: self.http_version.or_else(|| default.http_version.clone()),
+            compressed: self.compressed.or(default.compressed),
+            cert: self.cert.or_else(|| default.cert.clone()),
+            cert_type: self.cert_type.or_else(|| default.cert_type.clone()),
+            key: self.key.or_else(|| default.key.clone()),
+            key_password: self.key_password.or_else(|| default.key_password.clone()),
+            ca_cert: self.ca_cert.or_else(|| default.ca_cert.clone()),
+            proxy: self.proxy.or_else(|| default.proxy.clone()),
+            proxy_username: self
+                .proxy_username
+                .or_else(|| default.proxy_username.clone()),
+            proxy_password: self
+                .proxy_password
+                .or_else(|| default.proxy_password.clone()),
+            no_proxy: self.no_proxy.or_else(|| default.no_proxy.clone()),
+            unix_socket: self.unix_socket.or_else(|| default.unix_socket.clone()),
+            resolve: match (self.resolve, &default.resolve) {
+                (Some(mut resolve), Some(default)) => {
+                    for (k, v) in default {
+                        resolve.entry(k.clone()).or_insert_with(|| v.clone());
+                    }
+                    Some(resolve)
+                }
+                (resolve, default) => resolve.or_else(|| default.clone()),
+            },
+            ip_version: self.ip_version.or_else(|| default.ip_version.clone()),
+            cookies: self.cookies.or(default.cookies),
+        }
+    }
+
+    /// Resolves relative certificate and socket paths against `base_dir`.
+    pub fn resolve(self, base_dir: Option<&str>) -> Options {
+        let resolve =
+            |x: Option<String>| x.map(|x| resolve_path(base_dir, &x).display().to_string());
+        Options {
+            cert: resolve(self.cert),
+            key: resolve(self.key),
+            ca_cert: resolve(self.ca_cert),
+            unix_socket: resolve(self.unix_socket),
+            ..self
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(v) = &self.http_version {
+            if http_version(v).is_none() {
+                return Err(format!("unsupported http_version: {}", v));
+            }
+        }
+        if let Some(v) = &self.ip_version {
+            if ip_version(v).is_none() {
+                return Err(format!("unsupported ip_version: {}", v));
+            }
+        }
+        if let Some(resolve) = &self.resolve {
+            for host in resolve.keys() {
+                match host.rfind(':') {
+                    Some(i) if host[i + 1..].parse::<u16>().is_ok() => (),
+                    _ => return Err(format!("resolve: expected host:port, got {}", host)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, easy: &mut Easy) -> Result<(), curl::Error> {
+        easy.follow_location(self.follow_redirects.unwrap_or(true))?;
+        if let Some(n) = self.max_redirects {
+            easy.max_redirections(n)?;
+        }
+        if let Some(secs) = self.timeout {
+            easy.timeout(Duration::from_millis((secs * 1000.0) as u64))?;
+        }
+        if let Some(secs) = self.connect_timeout {
+            easy.connect_timeout(Duration::from_millis((secs * 1000.0) as u64))?;
+        }
+        if let Some(false) = self.verify_tls {
+            easy.ssl_verify_peer(false)?;
+            easy.ssl_verify_host(false)?;
+        }
+        if let Some(version) = self.http_version.as_ref().and_then(|x| http_version(x)) {
+            easy.http_version(version)?;
+        }
+        if let Some(true) = self.compressed {
+            // an empty string enables every encoding curl supports
+            easy.accept_encoding("")?;
+        }
+        if let Some(cert) = &self.cert {
+            easy.ssl_cert(cert)?;
+        }
+        if let Some(cert_type) = &self.cert_type {
+            easy.ssl_cert_type(cert_type)?;
+        }
+        if let Some(key) = &self.key {
+            easy.ssl_key(key)?;
+        }
+        if let Some(password) = &self.key_password {
+            easy.key_password(password)?;
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            easy.cainfo(ca_cert)?;
+        }
+        if let Some(proxy) = &self.proxy {
+            // an empty string also ignores the proxy environment variables
+            easy.proxy(proxy)?;
+        }
+        if let Some(username) = &self.proxy_username {
+            easy.proxy_username(username)?;
+        }
+        if let Some(password) = &self.proxy_password {
+            easy.proxy_password(password)?;
+        }
+        if let Some(hosts) = &self.no_proxy {
+            easy.noproxy(&hosts.join(","))?;
+        }
+        if let Some(path) = &self.unix_socket {
+            easy.unix_socket(path)?;
+        }
+        if let Some(resolve) = &self.resolve {
+            let mut list = List::new();
+            for (host, address) in resolve {
+                list.append(&format!("{}:{}", host, address))?;
+            }
+            easy.resolve(list)?;
+        }
+        if let Some(version) = self.ip_version.as_ref().and_then(|x| ip_version(x)) {
+            easy.ip_resolve(version)?;
+        }
+        Ok(())
+    }
+}
+
+fn http_version(s: &str) -> Option<HttpVersion> {
+    match s {
+        "1.0" | "1" => Some(HttpVersion::V10),
+        "1.1" => Some(HttpVersion::V11),
+        "2" | "2.0" => Some(HttpVersion::V2),
+        "2-prior-knowledge" => Some(HttpVersion::V2PriorKnowledge),
+        _ => None,
+    }
+}
+
+fn ip_version(s: &str) -> Option<IpResolve> {
+    match s {
+        "4" => Some(IpResolve::V4),
+        "6" => Some(IpResolve::V6),
+        "any" => Some(IpResolve::Any),
+        _ => None,
+    }
+}
+
+/// Accepts a string or a number, since YAML reads versions like `1.1` as numbers.
+fn deserialize_string<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        String(String),
+        Number(f64),
+    }
+
+    Ok(Option::<Value>::deserialize(d)?.map(|x| match x {
+        Value::String(s) => s,
+        Value::Number(n) => n.to_string(),
+    }))
+}
+
+/// Accepts a proxy URL, or `false` for no proxy, which is kept as an empty string.
+fn deserialize_proxy<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Proxy {
+        Url(String),
+        Enabled(bool),
+    }
+
+    match Option::<Proxy>::deserialize(d)? {
+        Some(Proxy::Url(url)) => Ok(Some(url)),
+        Some(Proxy::Enabled(false)) => Ok(Some(String::new())),
+        Some(Proxy::Enabled(true)) => Err(D::Error::custom("proxy must be a URL or false")),
+        None => Ok(None),
+    }
+}
+
+/// Accepts a list or a comma-separated string.
+fn deserialize_list<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        String(String),
+        List(Vec<String>),
+    }
+
+    Ok(Option::<List>::deserialize(d)?.map(|x| match x {
+        List::String(s) => s.split(',').map(|x| x.trim().to_string()).collect(),
+        List::List(v) => v,
+    }))
+}
@@ -0,0 +1,236 @@
+use serde_json::Value;
+
+const INDENT: &str = "  ";
+
+/// HTML elements that never have content.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// HTML elements whose content is copied as is.
+const RAW_ELEMENTS: &[&str] = &["script", "style", "pre", "textarea"];
+
+pub fn json(s: &str) -> Option<String> {
+    let json: Value = serde_json::from_str(s).ok()?;
+    serde_json::to_string_pretty(&json).ok()
+}
+
+/// Pretty-prints an XML or HTML document, returning the source language and the text.
+pub fn markup(s: &str) -> Option<(&'static str, String)> {
+    let s = s.trim();
+    if !s.starts_with('<') || !s.ends_with('>') {
+        return None;
+    }
+
+    let head = s.get(..14).unwrap_or(s).to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        Some(("html", indent(s, true)?))
+    } else {
+        Some(("xml", indent(s, false)?))
+    }
+}
+
+enum Token<'a> {
+    Open(&'a str, &'a str),
+    Close(&'a str),
+    Empty(&'a str),
+    Text(&'a str),
+    Raw(&'a str),
+}
+
+fn indent(s: &str, html: bool) -> Option<String> {
+    let tokens = tokenize(s, html)?;
+    let mut out = String::with_capacity(s.len() * 2);
+    let mut depth: usize = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::Open(name, tag) => {
+                // keep `<a>text</a>` on one line
+                if let (Some(Token::Text(text)), Some(Token::Close(close))) =
+                    (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    if close.eq_ignore_ascii_case(name) && !text.contains('\n') {
+                        push_line(&mut out, depth, &format!("{}{}</{}>", tag, text, close));
+                        i += 3;
+                        continue;
+                    }
+                }
+                push_line(&mut out, depth, tag);
+                if html && VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                    i += 1;
+                    continue;
+                }
+                depth += 1;
+            }
+            Token::Close(name) => {
+                depth = match depth.checked_sub(1) {
+                    Some(d) => d,
+                    None if html => 0,
+                    None => return None,
+                };
+                push_line(&mut out, depth, &format!("</{}>", name));
+            }
+            Token::Empty(tag) => push_line(&mut out, depth, tag),
+            Token::Raw(text) => {
+                out.push_str(text.trim_matches(|c| c == '\r' || c == '\n'));
+                out.push('\n');
+            }
+            Token::Text(text) => {
+                for line in text.lines() {
+                    let line = line.trim();
+                    if !line.is_empty() {
+                        push_line(&mut out, depth, line);
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+
+    if depth != 0 && !html {
+        return None;
+    }
+    out.pop();
+    Some(out)
+}
+
+fn push_line(out: &mut String, depth: usize, s: &str) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+    out.push_str(s);
+    out.push('\n');
+}
+
+fn tokenize(s: &str, html: bool) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                tokens.push(Token::Text(text));
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>")? + 3
+        } else {
+            tag_end(rest)? + 1
+        };
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") {
+            tokens.push(Token::Empty(tag));
+        } else if tag.starts_with("</") {
+            tokens.push(Token::Close(tag[2..tag.len() - 1].trim()));
+        } else {
+            let name = tag_name(tag);
+            tokens.push(Token::Open(name, tag));
+
+            if html && RAW_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                let close = format!("</{}", name.to_ascii_lowercase());
+                let end = rest.to_ascii_lowercase().find(&close)?;
+                if !rest[..end].trim().is_empty() {
+                    tokens.push(Token::Raw(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Finds the `>` that ends the tag at the start of `s`, skipping quoted attribute values.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn tag_name(tag: &str) -> &str {
+    let s = &tag[1..tag.len() - 1];
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml() {
+        let s =
+            r#"<?xml version="1.0"?><a x="1>2"><b>text</b><c/><!-- note --><d><e>1</e></d></a>"#;
+        let (lang, text) = markup(s).unwrap();
+        assert_eq!(lang, "xml");
+        assert_eq!(
+            text,
+            "<?xml version=\"1.0\"?>\n\
+             <a x=\"1>2\">\n\
+             \x20 <b>text</b>\n\
+             \x20 <c/>\n\
+             \x20 <!-- note -->\n\
+             \x20 <d>\n\
+             \x20   <e>1</e>\n\
+             \x20 </d>\n\
+             </a>"
+        );
+    }
+
+    #[test]
+    fn html() {
+        let s = "<!DOCTYPE html><html><head><meta charset=utf-8><script>if (a<b) {}</script>\
+                 </head><body><p>Hi <b>there</b></p><br></body></html>";
+        let (lang, text) = markup(s).unwrap();
+        assert_eq!(lang, "html");
+        assert_eq!(
+            text,
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             \x20 <head>\n\
+             \x20   <meta charset=utf-8>\n\
+             \x20   <script>\n\
+             if (a<b) {}\n\
+             \x20   </script>\n\
+             \x20 </head>\n\
+             \x20 <body>\n\
+             \x20   <p>\n\
+             \x20     Hi\n\
+             \x20     <b>there</b>\n\
+             \x20   </p>\n\
+             \x20   <br>\n\
+             \x20 </body>\n\
+             </html>"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(markup("not markup"), None);
+        assert_eq!(markup("<a><b></a>"), None);
+        assert_eq!(markup("<a attr=\"x></a>"), None);
+        assert_eq!(json("{\"a\":[1]}").unwrap(), "{\n  \"a\": [\n    1\n  ]\n}");
+        assert_eq!(json("<a/>"), None);
+    }
+}
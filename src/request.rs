@@ -1,64 +1,187 @@
-use crate::Group;
-use curl::easy::{Easy, Form, List};
-use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
-use serde::Deserialize;
+use crate::assert::{Assert, AssertResult};
+use crate::auth::Auth;
+use crate::capture::Capture;
+use crate::multimap::{deserialize_scalar, Entries, MultiMap};
+use crate::options::Options;
+use crate::sign::Signature;
+use crate::variable::{self, Substitution};
+use crate::{config, Group};
+use curl::easy::{Easy, Form, List, ReadError, SeekResult};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     collections::HashMap,
     error::Error,
     fmt::{Display, Formatter},
+    fs::File,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
 };
 
 static mut REQUEST_COUNTER: u32 = 0;
 
-type Map = HashMap<String, String>;
+/// The RFC 3986 unreserved characters, which are never percent-encoded.
+pub(crate) const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
 
 #[derive(Deserialize, Debug)]
 struct YamlRequest {
-    path: Option<Map>,
-    query: Option<Map>,
-    header: Option<Map>,
-    params: Option<Map>,
-    form: Option<Map>,
+    path: Option<MultiMap>,
+    query: Option<MultiMap>,
+    header: Option<MultiMap>,
+    params: Option<MultiMap>,
+    form: Option<Entries<FormPart>>,
     body: Option<String>,
+    body_file: Option<String>,
     json: Option<serde_json::Value>,
+    json_file: Option<String>,
+    graphql: Option<GraphQL>,
+    xml: Option<String>,
+    options: Option<Options>,
+    auth: Option<Auth>,
+    sign: Option<Signature>,
+    assert: Option<Assert>,
+    capture: Option<HashMap<String, Capture>>,
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct RequestParam {
-    pub path: Option<Map>,
-    pub query: Option<Map>,
-    pub header: Option<Map>,
+    pub path: Option<MultiMap>,
+    pub query: Option<MultiMap>,
+    pub header: Option<MultiMap>,
     pub body: Option<Body>,
+    pub assert: Option<Assert>,
+    pub capture: Option<HashMap<String, Capture>>,
+    pub options: Options,
+    pub auth: Option<Auth>,
+    pub sign: Option<Signature>,
 }
 
 impl YamlRequest {
-    fn into_param(self) -> serde_json::Result<RequestParam> {
-        let body = if self.params.is_some() {
-            Some(Body::Params(self.params.unwrap()))
-        } else if self.form.is_some() {
-            Some(Body::Form(self.form.unwrap()))
-        } else if self.json.is_some() {
-            Some(Body::Json(serde_json::to_string(&self.json.unwrap())?))
-        } else if self.body.is_some() {
-            Some(Body::Raw(self.body.unwrap()))
+    /// Converts to request params. Relative file paths are resolved against `base_dir`.
+    fn into_param(self, base_dir: Option<&str>) -> Result<RequestParam, Box<dyn Error>> {
+        let body = if let Some(params) = self.params {
+            Some(Body::Params(params))
+        } else if let Some(form) = self.form {
+            let form = form
+                .0
+                .into_iter()
+                .map(|(k, v)| (k, v.resolve(base_dir)))
+                .collect();
+            Some(Body::Form(form))
+        } else if let Some(json) = self.json {
+            Some(Body::Json(serde_json::to_string(&json)?))
+        } else if let Some(graphql) = self.graphql {
+            Some(Body::GraphQL(serde_json::to_string(&graphql)?))
+        } else if let Some(xml) = self.xml {
+            Some(Body::Xml(xml))
+        } else if let Some(path) = self.json_file {
+            Some(Body::JsonFile(resolve_path(base_dir, &path)))
+        } else if let Some(body) = self.body {
+            Some(Body::Raw(body))
         } else {
-            None
+            self.body_file
+                .map(|path| Body::File(resolve_path(base_dir, &path)))
         };
+        if let Some(sign) = &self.sign {
+            sign.validate()
+                .map_err(|e| ParseError::create(format!("sign: {}", e)))?;
+        }
+        if let Some(capture) = &self.capture {
+            for (name, c) in capture {
+                c.validate()
+                    .map_err(|e| ParseError::create(format!("capture {}: {}", name, e)))?;
+            }
+        }
+
         Ok(RequestParam {
             path: self.path,
             query: self.query,
             header: self.header,
             body,
+            assert: self.assert,
+            capture: self.capture,
+            options: self.options.unwrap_or_default(),
+            auth: self.auth,
+            sign: self.sign,
         })
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+struct GraphQL {
+    query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variables: Option<serde_json::Value>,
+    #[serde(rename = "operationName", skip_serializing_if = "Option::is_none")]
+    operation_name: Option<String>,
+}
+
+/// A multipart form field. A text value starting with `@` is the path of a file to upload.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FormPart {
+    Text(#[serde(deserialize_with = "deserialize_scalar")] String),
+    File {
+        file: String,
+        filename: Option<String>,
+        content_type: Option<String>,
+    },
+    Value {
+        #[serde(deserialize_with = "deserialize_scalar")]
+        value: String,
+        content_type: Option<String>,
+    },
+}
+
+impl FormPart {
+    fn resolve(self, base_dir: Option<&str>) -> FormPart {
+        match self {
+            FormPart::Text(s) if s.starts_with('@') => {
+                FormPart::Text(format!("@{}", resolve_path(base_dir, &s[1..]).display()))
+            }
+            FormPart::File {
+                file,
+                filename,
+                content_type,
+            } => FormPart::File {
+                file: resolve_path(base_dir, &file).display().to_string(),
+                filename,
+                content_type,
+            },
+            part => part,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Body {
-    Params(Map),
-    Form(Map),
+    Params(MultiMap),
+    Form(Vec<(String, FormPart)>),
     Json(String),
+    JsonFile(PathBuf),
+    GraphQL(String),
+    Xml(String),
     Raw(String),
+    File(PathBuf),
+}
+
+/// A request as it is sent, for authentication schemes that sign it.
+pub struct Message<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    pub header: Vec<(String, String)>,
+    pub payload: Payload<'a>,
+}
+
+pub enum Payload<'a> {
+    Bytes(&'a [u8]),
+    File(&'a Path),
+    /// Built by curl, so its content is not known in advance.
+    Multipart,
 }
 
 #[derive(Debug, Clone)]
@@ -68,6 +191,7 @@ pub struct Request {
     pub url: String,
     pub param: RequestParam,
     pub base_url: Option<String>,
+    pub group_id: String,
     pub raw: String,
 }
 
@@ -75,6 +199,7 @@ pub struct Request {
 pub struct Response {
     pub body: String,
     pub header: String,
+    pub code: u32,
     pub time: u32,
 }
 
@@ -85,6 +210,19 @@ impl Response {
         let pos = line.find(' ')?;
         Some(&line[pos + 1..])
     }
+
+    /// Returns the value of a header in the final response, ignoring case.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        let block = self.header.trim_end().rsplit("\r\n\r\n").next()?;
+        block.lines().skip(1).find_map(|line| {
+            let pos = line.find(':')?;
+            if line[..pos].trim().eq_ignore_ascii_case(name) {
+                Some(line[pos + 1..].trim())
+            } else {
+                None
+            }
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -106,58 +244,214 @@ impl Error for ParseError {}
 
 impl Request {
     pub fn parse(yaml: String, group: Option<Group>) -> Result<Request, Box<dyn Error>> {
-        let substituted = substitute_env(&yaml, group.as_ref());
-        let s = substituted.as_ref().unwrap_or(&yaml);
-
-        match get_method_url_line(s) {
-            (Some(line), remain) => match parse_method_url(line) {
-                Some((method, url)) => {
-                    if remain.is_empty() {
-                        Ok(Request {
-                            id: next_id(),
-                            method: method.to_uppercase(),
-                            url: url.into(),
-                            param: Default::default(),
-                            base_url: group.and_then(|x| x.base_url),
-                            raw: yaml,
-                        })
-                    } else {
-                        let req: YamlRequest = serde_yaml::from_str(remain)?;
-                        Ok(Request {
-                            id: next_id(),
-                            method: method.to_uppercase(),
-                            url: url.into(),
-                            param: req.into_param()?,
-                            base_url: group.and_then(|x| x.base_url),
-                            raw: yaml,
-                        })
-                    }
+        let base_dir = group.as_ref().and_then(|x| x.resolved_base_dir());
+        let mut sub =
+            Substitution::new(group.as_ref()).map_err(|e| ParseError::create(e.to_string()))?;
+
+        let (line, remain) = match get_method_url_line(&yaml) {
+            (Some(line), remain) => (sub.text(line), remain),
+            _ => return Err(ParseError::create("empty content".into())),
+        };
+        let (method, url) = match parse_method_url(&line) {
+            Some(x) => x,
+            None => return Err(ParseError::create(format!("invalid format: {}", line))),
+        };
+
+        // Variables are substituted after parsing so that values containing YAML syntax
+        // can't break the document, and so that `json:` keeps the types of the values.
+        let mut param: RequestParam = if is_blank(remain) {
+            Default::default()
+        } else {
+            let value: serde_yaml::Value = serde_yaml::from_str(remain)?;
+            let mut req: YamlRequest = from_substituted(substitute_request(value, &mut sub))?;
+            req.json = req.json.map(|x| sub.json(x));
+            if let Some(graphql) = &mut req.graphql {
+                graphql.variables = graphql.variables.take().map(|x| sub.json(x));
+                graphql.operation_name = graphql.operation_name.as_ref().map(|x| sub.text(x));
+            }
+            req.into_param(base_dir.as_deref())?
+        };
+
+        if let Some(options) = group.as_ref().and_then(|x| x.options.as_ref()) {
+            param.options = param.options.merge(&substitute_value(options, &mut sub)?);
+        }
+        param.options = param.options.resolve(base_dir.as_deref());
+        param.options.validate().map_err(ParseError::create)?;
+        // a substituted value must not be able to add headers or split the request line
+        check_line("url", url)?;
+        for (k, v) in param.header.iter().flatten() {
+            check_line("header name", k)?;
+            check_line(&format!("header {}", k), v)?;
+        }
+        replace_path_params(url, param.path.as_ref()).map_err(ParseError::create)?;
+        if param.auth.is_none() {
+            if let Some(auth) = group.as_ref().and_then(|x| x.auth.as_ref()) {
+                param.auth = Some(substitute_value(auth, &mut sub)?);
+            }
+        }
+        if param.sign.is_none() {
+            if let Some(sign) = group.as_ref().and_then(|x| x.sign.as_ref()) {
+                let sign: Signature = substitute_value(sign, &mut sub)?;
+                sign.validate()
+                    .map_err(|e| ParseError::create(format!("group sign: {}", e)))?;
+                param.sign = Some(sign);
+            }
+        }
+
+        if !sub.missing().is_empty() && group.as_ref().and_then(|x| x.strict).unwrap_or(false) {
+            return Err(ParseError::create(format!(
+                "undefined variables: {}",
+                sub.missing().join(", ")
+            )));
+        }
+
+        Ok(Request {
+            id: next_id(),
+            method: method.to_uppercase(),
+            url: url.into(),
+            param,
+            group_id: group.as_ref().map(|x| x.id.clone()).unwrap_or_default(),
+            base_url: group.and_then(|x| x.base_url),
+            raw: yaml,
+        })
+    }
+
+    /// Returns the non-empty documents of a buffer. They should be parsed one at a time,
+    /// right before each is performed, so that captured variables reach later requests.
+    pub fn documents(yaml: &str) -> Vec<&str> {
+        Request::split_documents(yaml)
+            .into_iter()
+            .map(|(_, doc)| doc)
+            .filter(|doc| get_method_url_line(doc).0.is_some())
+            .collect()
+    }
+
+    /// Splits a buffer into documents separated by `---` lines. Each document is returned
+    /// with the line number it starts at.
+    pub fn split_documents(s: &str) -> Vec<(usize, &str)> {
+        let mut v = Vec::new();
+        let mut start = 0;
+        let mut start_line = 0;
+        let mut offset = 0;
+
+        for (i, line) in s.split('\n').enumerate() {
+            if line.trim_end() == "---" {
+                v.push((start_line, &s[start..offset]));
+                start = offset + line.len() + 1;
+                start_line = i + 1;
+            }
+            offset += line.len() + 1;
+        }
+
+        v.push((start_line, &s[start.min(s.len())..]));
+        v
+    }
+
+    /// Returns the document that a line is in. A `---` line belongs to the document before it,
+    /// and lines before the first request belong to the first one.
+    pub fn document_at(s: &str, line: usize) -> &str {
+        let documents: Vec<_> = Request::split_documents(s)
+            .into_iter()
+            .filter(|(_, doc)| get_method_url_line(doc).0.is_some())
+            .collect();
+        documents
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= line)
+            .or_else(|| documents.first())
+            .map(|(_, doc)| *doc)
+            .unwrap_or_default()
+    }
+
+    pub fn check(&self, response: &Response) -> Vec<AssertResult> {
+        match &self.param.assert {
+            Some(assert) => assert.check(response),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stores the values named in the `capture:` section as variables of the request's group,
+    /// returning the names that could not be found in the response.
+    pub fn capture(&self, response: &Response) -> Vec<String> {
+        let mut missing = Vec::new();
+        if let Some(capture) = &self.param.capture {
+            for (name, c) in capture {
+                match c.extract(response) {
+                    Some(value) => variable::set(&self.group_id, name.clone(), value),
+                    None => missing.push(name.clone()),
                 }
-                None => Err(ParseError::create(format!("invalid format: {}", line))),
-            },
-            _ => Err(ParseError::create("empty content".into())),
+            }
+        }
+        missing
+    }
+
+    /// Returns the messages in the `errors` array of a GraphQL response.
+    pub fn graphql_errors(&self, response: &Response) -> Vec<String> {
+        if let Some(Body::GraphQL(_)) = &self.param.body {
+            let body: serde_json::Value = match serde_json::from_str(&response.body) {
+                Ok(body) => body,
+                Err(_) => return Vec::new(),
+            };
+            if let Some(errors) = body.get("errors").and_then(|x| x.as_array()) {
+                return errors
+                    .iter()
+                    .map(|e| match e.get("message").and_then(|x| x.as_str()) {
+                        Some(message) => message.to_string(),
+                        None => e.to_string(),
+                    })
+                    .collect();
+            }
+        }
+        Vec::new()
+    }
+
+    /// Returns warnings about combinations that are sent as written but that servers may
+    /// not expect.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let no_body = ["GET", "HEAD", "DELETE", "OPTIONS", "TRACE", "CONNECT"];
+        if self.param.body.is_some() && no_body.contains(&self.method.as_str()) {
+            warnings.push(format!(
+                "{} request has a body, some servers and proxies ignore or reject it",
+                self.method
+            ));
+        }
+        warnings
+    }
+
+    fn has_header(&self, name: &str) -> bool {
+        match &self.param.header {
+            Some(header) => header.into_iter().any(|(k, _)| k.eq_ignore_ascii_case(name)),
+            None => false,
         }
     }
 
     pub fn perform(&self) -> Result<Response, Box<dyn Error>> {
         let mut easy = Easy::new();
-        easy.follow_location(true)?;
+        self.param.options.apply(&mut easy)?;
+        if self.param.options.cookies.unwrap_or(true) {
+            // cookies are read when the request starts and saved when `easy` is dropped
+            let jar =
+                config::cookie_path(&self.group_id).map_err(|e| format!("cookie jar: {}", e))?;
+            easy.cookie_file(&jar)?;
+            easy.cookie_jar(&jar)?;
+        }
 
         let mut url = match &self.base_url {
             Some(url) if !url_is_absolute(&self.url) => url.clone(),
+            // the host of a socket request is only used for the Host header
+            None if self.param.options.unix_socket.is_some() && !url_is_absolute(&self.url) => {
+                "http://localhost".into()
+            }
             _ => String::new(),
         };
         if !url.ends_with("/") {
             url.push(b'/' as _);
         }
 
-        if let Some(path_params) = &self.param.path {
-            let path = replace_path_params(self.url.clone(), path_params);
-            url.push_str(if path.starts_with("/") { &path[1..] } else { &path } );
-        } else {
-            let path = &self.url;
-            url.push_str(if path.starts_with("/") { &path[1..] } else { path } );
-        };
+        let path =
+            replace_path_params(&self.url, self.param.path.as_ref()).map_err(ParseError::create)?;
+        url.push_str(path.strip_prefix('/').unwrap_or(&path));
 
         if url.starts_with("/") {
             url = String::from(&url[1..]);
@@ -172,44 +466,125 @@ impl Request {
             easy.url(&url)?;
         }
 
-        let mut list = List::new();
-        if let Some(m) = &self.param.header {
-            for (k, v) in m {
-                list.append(&format!("{}: {}", &k, &v))?;
-            }
-        }
+        let mut header: Vec<(String, String)> = match &self.param.header {
+            Some(m) => m.into_iter().cloned().collect(),
+            None => Vec::new(),
+        };
+        let mut upload = None;
+        let mut content_type = None;
+        let params;
+        let mut payload = Payload::Bytes(&[]);
 
         let method = self.method.to_uppercase();
         easy.custom_request(&method)?;
 
-        match method.as_str() {
-            "POST" | "PUT" | "PATCH" => match &self.param.body {
-                Some(Body::Form(m)) => {
-                    let mut form = Form::new();
-                    for (k, v) in m {
-                        form.part(&k).contents(v.as_bytes()).add()?;
+        match &self.param.body {
+            Some(Body::Form(m)) => {
+                let mut form = Form::new();
+                for (k, v) in m {
+                    let mut part = form.part(k);
+                    match v {
+                        FormPart::Text(s) if s.starts_with('@') => {
+                            part.file(check_file(Path::new(&s[1..]))?);
+                        }
+                        FormPart::Text(s) => {
+                            part.contents(s.as_bytes());
+                        }
+                        FormPart::File {
+                            file,
+                            filename,
+                            content_type,
+                        } => {
+                            part.file(check_file(Path::new(file))?);
+                            if let Some(filename) = filename {
+                                part.filename(filename);
+                            }
+                            if let Some(content_type) = content_type {
+                                part.content_type(content_type);
+                            }
+                        }
+                        FormPart::Value {
+                            value,
+                            content_type,
+                        } => {
+                            part.contents(value.as_bytes());
+                            if let Some(content_type) = content_type {
+                                part.content_type(content_type);
+                            }
+                        }
                     }
-                    easy.httppost(form)?;
-                }
-                Some(Body::Params(m)) => {
-                    let s = http_build_query(m);
-                    easy.post_fields_copy(s.as_bytes())?;
-                    list.append("content-type: application/x-www-form-urlencoded")?;
+                    part.add()?;
                 }
-                Some(Body::Json(s)) => {
-                    easy.post_field_size(s.len() as u64)?;
-                    easy.post_fields_copy(s.as_bytes())?;
-                    list.append("content-type: application/json")?;
-                }
-                Some(Body::Raw(s)) => {
-                    easy.post_field_size(s.len() as u64)?;
-                    easy.post_fields_copy(s.as_bytes())?;
-                }
-                None => (),
-            },
-            _ => (),
+                easy.httppost(form)?;
+                payload = Payload::Multipart;
+            }
+            Some(Body::Params(m)) => {
+                params = http_build_query(m);
+                easy.post_fields_copy(params.as_bytes())?;
+                payload = Payload::Bytes(params.as_bytes());
+                content_type = Some("application/x-www-form-urlencoded");
+            }
+            Some(Body::Json(s)) | Some(Body::GraphQL(s)) => {
+                easy.post_field_size(s.len() as u64)?;
+                easy.post_fields_copy(s.as_bytes())?;
+                payload = Payload::Bytes(s.as_bytes());
+                content_type = Some("application/json");
+            }
+            Some(Body::Xml(s)) => {
+                easy.post_field_size(s.len() as u64)?;
+                easy.post_fields_copy(s.as_bytes())?;
+                payload = Payload::Bytes(s.as_bytes());
+                content_type = Some("application/xml");
+            }
+            Some(Body::Raw(s)) => {
+                easy.post_field_size(s.len() as u64)?;
+                easy.post_fields_copy(s.as_bytes())?;
+                payload = Payload::Bytes(s.as_bytes());
+            }
+            Some(Body::JsonFile(path)) => {
+                upload = Some(open_upload(&mut easy, path)?);
+                payload = Payload::File(path);
+                content_type = Some("application/json");
+            }
+            Some(Body::File(path)) => {
+                upload = Some(open_upload(&mut easy, path)?);
+                payload = Payload::File(path);
+            }
+            None => (),
         }
 
+        if let Some(content_type) = content_type {
+            if !self.has_header("content-type") {
+                header.push(("content-type".into(), content_type.into()));
+            }
+        }
+
+        let mut message = Message {
+            method: &method,
+            url: &url,
+            header,
+            payload,
+        };
+        // a bearer token is added before signing so that `{header.Authorization}` can be
+        // signed, while an AWS signature must cover every header, including the signature
+        let auth = self.param.auth.as_ref();
+        let (before, after) = match auth {
+            Some(Auth::AwsSigv4(_)) => (None, auth),
+            _ => (auth, None),
+        };
+        if let Some(auth) = before {
+            auth.apply(&mut easy, &mut message, &self.param.options)?;
+        }
+        if let Some(sign) = &self.param.sign {
+            sign.sign(&mut message)?;
+        }
+        if let Some(auth) = after {
+            auth.apply(&mut easy, &mut message, &self.param.options)?;
+        }
+        let mut list = List::new();
+        for (k, v) in &message.header {
+            list.append(&format!("{}: {}", k, v))?;
+        }
         easy.http_headers(list)?;
 
         let mut body = Vec::new();
@@ -228,17 +603,115 @@ impl Request {
                 true
             })?;
 
-            transfer.perform()?;
+            if let Some(mut file) = upload.as_ref() {
+                transfer.read_function(move |buf| file.read(buf).map_err(|_| ReadError::Abort))?;
+                // curl rewinds the body to send it again after a redirect or an auth challenge
+                transfer.seek_function(move |pos| match file.seek(pos) {
+                    Ok(_) => SeekResult::Ok,
+                    Err(_) => SeekResult::Fail,
+                })?;
+            }
+
+            transfer.perform().map_err(transfer_error)?;
+        }
+
+        let code = easy.response_code()?;
+        if code == 401 {
+            if let Some(Auth::Oauth2(client)) = &self.param.auth {
+                client.invalidate();
+            }
         }
 
         Ok(Response {
             body: String::from_utf8_lossy(&body).to_string(),
             header: String::from_utf8_lossy(&header).to_string(),
+            code,
             time: easy.total_time()?.as_millis() as u32,
         })
     }
 }
 
+/// Adds a hint to TLS errors, which curl reports with little context.
+fn transfer_error(e: curl::Error) -> Box<dyn Error> {
+    let extra = e.extra_description().unwrap_or_else(|| e.description());
+    let hint = if e.is_peer_failed_verification() {
+        "the server certificate could not be verified, set `ca_cert` to the CA bundle that \
+         signed it, or `verify_tls: false`"
+    } else if e.is_ssl_certproblem() || extra.contains("private key") {
+        "the client certificate could not be used, check `cert`, `key` and `key_password`"
+    } else if e.is_ssl_cacert_badfile() {
+        "the CA bundle in `ca_cert` could not be read"
+    } else if e.is_ssl_connect_error() {
+        "the TLS handshake failed, the server may require a client certificate (`cert` and `key`)"
+    } else {
+        return Box::new(e);
+    };
+    format!("TLS error: {}\n{}", extra, hint).into()
+}
+
+/// Substitutes variables in every section except `json:` and `graphql:`, which are handled
+/// separately: types are kept, and `$name` in a GraphQL query is a GraphQL variable.
+fn substitute_request(value: serde_yaml::Value, sub: &mut Substitution) -> serde_yaml::Value {
+    match value {
+        serde_yaml::Value::Mapping(m) => serde_yaml::Value::Mapping(
+            m.into_iter()
+                .map(|(k, v)| match k.as_str() {
+                    Some("json") | Some("graphql") => (k, v),
+                    _ => (k, sub.yaml(v)),
+                })
+                .collect(),
+        ),
+        value => sub.yaml(value),
+    }
+}
+
+/// Substitutes variables in a value from the group config.
+fn substitute_value<T: Serialize + DeserializeOwned>(
+    value: &T,
+    sub: &mut Substitution,
+) -> serde_yaml::Result<T> {
+    from_substituted(sub.yaml(serde_yaml::to_value(value)?))
+}
+
+/// Deserializes a substituted value through YAML text, which accepts a number or boolean
+/// for a string field, as the request text did before values were substituted after parsing.
+fn from_substituted<T: DeserializeOwned>(value: serde_yaml::Value) -> serde_yaml::Result<T> {
+    serde_yaml::from_str(&serde_yaml::to_string(&value)?)
+}
+
+fn is_blank(s: &str) -> bool {
+    s.lines().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('#')
+    })
+}
+
+fn check_file(path: &Path) -> Result<&Path, Box<dyn Error>> {
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(ParseError::create(format!(
+            "file not found: {}",
+            path.display()
+        )))
+    }
+}
+
+/// Sets up `easy` to stream the file as the request body.
+fn open_upload(easy: &mut Easy, path: &Path) -> Result<File, Box<dyn Error>> {
+    let file = File::open(check_file(path)?)?;
+    easy.post(true)?;
+    easy.post_field_size(file.metadata()?.len())?;
+    Ok(file)
+}
+
+pub(crate) fn resolve_path(base_dir: Option<&str>, path: &str) -> PathBuf {
+    match base_dir {
+        Some(dir) => Path::new(dir).join(path),
+        None => PathBuf::from(path),
+    }
+}
+
 fn parse_method_url(s: &str) -> Option<(&str, &str)> {
     let s = s.trim();
     let i = s.find(" ")?;
@@ -280,7 +753,7 @@ fn next_id() -> u32 {
     }
 }
 
-fn http_build_query(query: &Map) -> String {
+pub(crate) fn http_build_query(query: &MultiMap) -> String {
     let mut s = String::new();
     for (key, value) in query {
         s.push_str(&utf8_percent_encode(key, NON_ALPHANUMERIC).to_string());
@@ -292,92 +765,316 @@ fn http_build_query(query: &Map) -> String {
     s
 }
 
-fn substitute_env(s: &str, group: Option<&Group>) -> Option<String> {
-    let env = group?.env.as_ref()?;
-    if env.is_empty() {
+#[inline]
+fn url_is_absolute(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Returns the length of the `scheme://` prefix of a URL. A `://` after the path starts, as
+/// in `/a?next=http://b/c`, is not a scheme.
+fn scheme_len(url: &str) -> Option<usize> {
+    let i = url.find("://")?;
+    if url[..i].contains(&['/', '?', '#'][..]) {
         None
     } else {
-        Some(substitute(s, env))
+        Some(i + 3)
     }
 }
 
-fn substitute(s: &str, map: &Map) -> String {
-    let bytes = s.as_bytes();
-    let len = bytes.len();
-    let mut v = Vec::with_capacity(len);
+/// Splits a URL into the host as sent in the `Host` header, the path and the query.
+pub(crate) fn split_url(url: &str) -> (&str, &str, &str) {
+    let (scheme, rest) = match scheme_len(url) {
+        Some(i) => (&url[..i - 3], &url[i..]),
+        None => ("http", url),
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, rest) = match rest.find(&['/', '?'][..]) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let (path, query) = match rest.find('?') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let path = if path.is_empty() { "/" } else { path };
 
-    let mut i = 0;
-    let mut j;
-    while i < len - 1 {
-        let next = bytes[i + 1];
-        let brace = next == b'{';
-        if bytes[i] == b'$' && (is_alpha_digit(next) || brace) {
-            j = i + if brace { 2 } else { 1 };
-            while j < len && (is_alpha_digit(bytes[j]) || bytes[j] == b'_') {
-                j += 1;
-            }
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let default_port = if scheme.eq_ignore_ascii_case("https") {
+        ":443"
+    } else {
+        ":80"
+    };
+    let host = host.strip_suffix(default_port).unwrap_or(host);
+    (host, path, query)
+}
+
+fn check_line(what: &str, s: &str) -> Result<(), Box<ParseError>> {
+    if s.contains(&['\r', '\n'][..]) {
+        Err(ParseError::create(format!(
+            "{} contains a line break: {:?}",
+            what, s
+        )))
+    } else {
+        Ok(())
+    }
+}
 
-            let name = if brace {
-                if j == len || bytes[j] != b'}' || j == i + 2 {
-                    v.extend_from_slice(&bytes[i..j]);
-                    i = j;
-                    continue;
-                } else {
-                    &bytes[i + 2..j]
+/// Replaces `{name}` placeholders and `:name` segments in the path of a URL with the
+/// percent-encoded values in `params`. Every placeholder and segment must have a value.
+fn replace_path_params(url: &str, params: Option<&MultiMap>) -> Result<String, String> {
+    let start = match scheme_len(url) {
+        Some(i) => url[i..]
+            .find(&['/', '?', '#'][..])
+            .map_or(url.len(), |x| x + i),
+        None => 0,
+    };
+    let end = url[start..]
+        .find(&['?', '#'][..])
+        .map_or(url.len(), |x| x + start);
+    let value = |name: &str| params.and_then(|x| x.get(name));
+
+    let mut path = String::from(&url[..start]);
+    let mut missing = Vec::new();
+    for (i, segment) in url[start..end].split('/').enumerate() {
+        if i > 0 {
+            path.push('/');
+        }
+        if segment.len() > 1 && segment.starts_with(':') {
+            match value(&segment[1..]) {
+                Some(v) => path.push_str(&utf8_percent_encode(v, UNRESERVED).to_string()),
+                None => {
+                    path.push_str(segment);
+                    missing.push(&segment[1..]);
                 }
-            } else {
-                &bytes[i + 1..j]
-            };
+            }
+            continue;
+        }
 
-            let name = String::from_utf8_lossy(name);
-            if let Some(value) = map.get(&*name) {
-                v.extend_from_slice(value.as_bytes());
+        let mut rest = segment;
+        while let Some(open) = rest.find('{') {
+            let close = match rest[open..].find('}') {
+                Some(x) => open + x,
+                None => break,
+            };
+            path.push_str(&rest[..open]);
+            let name = &rest[open + 1..close];
+            if rest[..open].ends_with('$') {
+                // an undefined variable, not a placeholder
+                path.push_str(&rest[open..=close]);
             } else {
-                if brace {
-                    v.extend_from_slice(&bytes[i..=j]);
-                } else {
-                    v.extend_from_slice(&bytes[i..j]);
+                match value(name) {
+                    Some(v) => path.push_str(&utf8_percent_encode(v, UNRESERVED).to_string()),
+                    None => missing.push(name),
                 }
             }
-            i = j + if brace { 1 } else { 0 };
-        } else {
-            v.push(bytes[i]);
-            i += 1;
+            rest = &rest[close + 1..];
         }
+        path.push_str(rest);
     }
 
-    if i == len - 1 {
-        v.push(bytes[i]);
+    if !missing.is_empty() {
+        return Err(format!("missing path params: {}", missing.join(", ")));
     }
-
-    String::from_utf8_lossy(&v).to_string()
+    path.push_str(&url[end..]);
+    Ok(path)
 }
 
-#[inline]
-fn is_alpha_digit(b: u8) -> bool {
-    (b >= b'0' && b <= b'9') || (b >= b'a' && b <= b'z') || (b >= b'A' && b <= b'Z')
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::Server;
 
-#[inline]
-fn url_is_absolute(url: &str) -> bool {
-    url.starts_with("http://") || url.starts_with("https://")
-}
+    fn params(pairs: &[(&str, &str)]) -> MultiMap {
+        MultiMap::from(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>(),
+        )
+    }
 
-fn replace_path_params(mut path: String, params: &Map) -> String {
-    let end_slash = if let Some(&b'/') = path.as_bytes().last() {
-        true
-    } else {
-        path.push(b'/' as _);
-        false
-    };
+    #[test]
+    fn path_params() {
+        let p = params(&[("id", "a b/c"), ("name", "x")]);
+        assert_eq!(
+            replace_path_params("http://host/users/{id}/{name}.json?q={id}", Some(&p)).unwrap(),
+            "http://host/users/a%20b%2Fc/x.json?q={id}"
+        );
+        assert_eq!(
+            replace_path_params("/users/:id/posts", Some(&p)).unwrap(),
+            "/users/a%20b%2Fc/posts"
+        );
+        assert_eq!(
+            replace_path_params("/users/${id}/{name}", Some(&p)).unwrap(),
+            "/users/${id}/x"
+        );
+    }
 
-    for (k, v) in params {
-        path = path.replace(&format!("/:{}/", k), &format!("/{}/", v));
+    #[test]
+    fn path_params_scheme_in_query() {
+        let p = params(&[("id", "1")]);
+        assert_eq!(
+            replace_path_params("/users/{id}?next=http://a/b", Some(&p)).unwrap(),
+            "/users/1?next=http://a/b"
+        );
+        assert_eq!(
+            replace_path_params("http://host:8080?next=http://a/{id}", None).unwrap(),
+            "http://host:8080?next=http://a/{id}"
+        );
     }
 
-    if !end_slash {
-        path.pop();
+    #[test]
+    fn path_params_missing() {
+        let p = params(&[("id", "1")]);
+        assert_eq!(
+            replace_path_params("/users/{id}/{a}/:b", Some(&p)).unwrap_err(),
+            "missing path params: a, b"
+        );
+        assert_eq!(
+            replace_path_params("/users/:id", None).unwrap_err(),
+            "missing path params: id"
+        );
     }
 
-    return path;
-}
\ No newline at end of file
+    fn group(env: &[(&str, &str)]) -> Group {
+        Group {
+            env: Some(
+                env.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn line_breaks_in_substituted_values() {
+        let g = group(&[("token", "abc\r\nX-Injected: 1"), ("path", "a\nb")]);
+        let err = Request::parse("GET /\nheader:\n  Token: $token".into(), Some(g.clone()));
+        assert!(err.unwrap_err().to_string().contains("line break"));
+        let err = Request::parse("GET /\nheader:\n  $token: 1".into(), Some(g.clone()));
+        assert!(err.unwrap_err().to_string().contains("line break"));
+        let err = Request::parse("GET /$path".into(), Some(g.clone()));
+        assert!(err.unwrap_err().to_string().contains("line break"));
+        assert!(Request::parse("GET /\nheader:\n  Token: ok".into(), Some(g)).is_ok());
+    }
+
+    #[test]
+    fn strict_variables() {
+        let yaml = "GET /$a?x=${b}\nheader:\n  X: ${c:-x}\n  Y: $a\n  Z: $d";
+        let mut g = group(&[("d", "1")]);
+        assert!(Request::parse(yaml.into(), Some(g.clone())).is_ok());
+        g.strict = Some(true);
+        let err = Request::parse(yaml.into(), Some(g)).unwrap_err();
+        assert_eq!(err.to_string(), "undefined variables: a, b");
+    }
+
+    #[test]
+    fn split_urls() {
+        assert_eq!(
+            split_url("https://user@host:443/a/b?x=1#f"),
+            ("host", "/a/b", "x=1")
+        );
+        assert_eq!(split_url("http://host:8080"), ("host:8080", "/", ""));
+        assert_eq!(
+            split_url("/a?next=http://b/c"),
+            ("", "/a", "next=http://b/c")
+        );
+    }
+
+    #[test]
+    fn typed_variables() {
+        let g = group(&[
+            ("t", "30"),
+            ("v", "false"),
+            ("c", "201"),
+            ("n", "5"),
+            ("pw", "007"),
+        ]);
+        let yaml = "GET /\n\
+                    options:\n  timeout: ${t}\n  verify_tls: $v\n\
+                    assert:\n  status: $c\n\
+                    auth:\n  type: basic\n  username: $n\n  password: $pw";
+        let request = Request::parse(yaml.into(), Some(g)).unwrap();
+        assert_eq!(request.param.options.timeout, Some(30.0));
+        assert_eq!(request.param.options.verify_tls, Some(false));
+        match request.param.auth {
+            Some(Auth::Basic { username, password }) => {
+                assert_eq!((username.as_str(), password.as_str()), ("5", "007"));
+            }
+            auth => panic!("{:?}", auth),
+        }
+
+        let response = Response {
+            body: String::new(),
+            header: String::new(),
+            code: 201,
+            time: 0,
+        };
+        let results = request.param.assert.unwrap().check(&response);
+        assert!(results.iter().all(|x| x.pass), "{:?}", results);
+    }
+
+    #[test]
+    fn upload_file_after_redirect() {
+        let server = Server::start(vec![
+            "HTTP/1.1 307 Temporary Redirect\nLocation: /b",
+            "HTTP/1.1 200 OK\n\nok",
+        ]);
+        let path = std::env::temp_dir().join("put-upload-after-redirect.txt");
+        std::fs::write(&path, "file body").unwrap();
+
+        let yaml = format!(
+            "POST {}/a\nbody_file: {}\noptions:\n  cookies: false",
+            server.url,
+            path.display()
+        );
+        let response = Request::parse(yaml, None).unwrap().perform().unwrap();
+        assert_eq!(response.code, 200);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("POST /a ") && requests[0].ends_with("file body"));
+        assert!(requests[1].starts_with("POST /b ") && requests[1].ends_with("file body"));
+    }
+
+    #[test]
+    fn split_documents() {
+        let text = "GET /a\n---\nGET /b\nheader:\n  X: 1\n---  \n\nGET /c";
+        assert_eq!(
+            Request::split_documents(text),
+            [
+                (0, "GET /a\n"),
+                (2, "GET /b\nheader:\n  X: 1\n"),
+                (6, "\nGET /c")
+            ]
+        );
+        assert_eq!(
+            Request::split_documents("GET /a\r\n---\r\nGET /b\r\n"),
+            [(0, "GET /a\r\n"), (2, "GET /b\r\n")]
+        );
+        assert_eq!(
+            Request::split_documents("---\nGET /a\n---\n"),
+            [(0, ""), (1, "GET /a\n"), (3, "")]
+        );
+        assert_eq!(
+            Request::documents("---\n# note\n---\nGET /a\n---\n\n"),
+            ["GET /a\n"]
+        );
+    }
+
+    #[test]
+    fn document_at() {
+        let text = "GET /a\n---\nGET /b\n\n---\nGET /c";
+        assert_eq!(Request::document_at(text, 0), "GET /a\n");
+        assert_eq!(Request::document_at(text, 1), "GET /a\n");
+        assert_eq!(Request::document_at(text, 3), "GET /b\n\n");
+        assert_eq!(Request::document_at(text, 5), "GET /c");
+        assert_eq!(Request::document_at(text, 99), "GET /c");
+
+        let text = "---\r\nGET /a\r\n---\r\nGET /b\r\n";
+        assert_eq!(Request::document_at(text, 0), "GET /a\r\n");
+        assert_eq!(Request::document_at(text, 2), "GET /a\r\n");
+        assert_eq!(Request::document_at(text, 3), "GET /b\r\n");
+        assert_eq!(Request::document_at("", 0), "");
+    }
+}
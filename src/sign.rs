@@ -0,0 +1,265 @@
+use crate::request::{split_url, Message, Payload};
+use crate::variable;
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::{error::Error, fs};
+
+const PLACEHOLDERS: &[&str] = &["method", "url", "path", "query", "timestamp", "body"];
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    Sha1,
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// An HMAC signature header, from the `sign:` section of a request or the group default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    /// Name of the header the signature is sent in.
+    pub header: String,
+    pub secret: String,
+    /// The string to sign, with `{method}`, `{url}`, `{path}`, `{query}`, `{timestamp}`,
+    /// `{body}` and `{header.<name>}` placeholders. `{{` and `}}` are literal braces.
+    pub template: String,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Header that the timestamp used in the signature is sent in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_header: Option<String>,
+}
+
+impl Signature {
+    pub fn validate(&self) -> Result<(), String> {
+        render(&self.template, |name| {
+            if PLACEHOLDERS.contains(&name) || name.starts_with("header.") {
+                Ok(String::new())
+            } else {
+                Err(format!("unknown placeholder {{{}}}", name))
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Adds the signature header, computed from the message as it will be sent.
+    pub fn sign(&self, message: &mut Message) -> Result<(), Box<dyn Error>> {
+        let timestamp = variable::timestamp().to_string();
+        if let Some(name) = &self.timestamp_header {
+            message.header.push((name.clone(), timestamp.clone()));
+        }
+
+        let (_, path, query) = split_url(message.url);
+        let data = render(&self.template, |name| {
+            Ok(match name {
+                "method" => message.method.to_string(),
+                "url" => message.url.to_string(),
+                "path" => path.to_string(),
+                "query" => query.to_string(),
+                "timestamp" => timestamp.clone(),
+                "body" => body(&message.payload)?,
+                _ => {
+                    let header = &name["header.".len()..];
+                    message
+                        .header
+                        .iter()
+                        .find(|(k, _)| k.eq_ignore_ascii_case(header))
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default()
+                }
+            })
+        })?;
+
+        let key = self.secret.as_bytes();
+        let digest = match self.algorithm {
+            Algorithm::Sha1 => mac::<Hmac<Sha1>>(key, data.as_bytes()),
+            Algorithm::Sha256 => mac::<Hmac<Sha256>>(key, data.as_bytes()),
+            Algorithm::Sha512 => mac::<Hmac<Sha512>>(key, data.as_bytes()),
+        };
+        let value = match self.encoding {
+            Encoding::Hex => hex::encode(digest),
+            Encoding::Base64 => base64::encode(digest),
+        };
+        message.header.push((self.header.clone(), value));
+        Ok(())
+    }
+}
+
+/// Replaces each `{name}` in the template with the value returned by `f`.
+fn render<F>(template: &str, mut f: F) -> Result<String, String>
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(i) = rest.find(&['{', '}'][..]) {
+        out.push_str(&rest[..i]);
+        let s = &rest[i..];
+        if s.starts_with("{{") || s.starts_with("}}") {
+            out.push_str(&s[..1]);
+            rest = &s[2..];
+        } else if s.starts_with('}') {
+            return Err("unmatched } in template".into());
+        } else {
+            let end = s.find('}').ok_or("unclosed { in template")?;
+            out.push_str(&f(s[1..end].trim())?);
+            rest = &s[end + 1..];
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn body(payload: &Payload) -> Result<String, String> {
+    match payload {
+        Payload::Bytes(bytes) => Ok(String::from_utf8_lossy(bytes).to_string()),
+        Payload::File(path) => fs::read(path)
+            .map(|x| String::from_utf8_lossy(&x).to_string())
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Payload::Multipart => Err("a multipart body can't be signed".into()),
+    }
+}
+
+pub(crate) fn mac<M: Mac + NewMac>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = M::new_varkey(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(template: &str, algorithm: Algorithm, encoding: Encoding) -> Signature {
+        Signature {
+            header: "X-Signature".into(),
+            secret: "Jefe".into(),
+            template: template.into(),
+            algorithm,
+            encoding,
+            timestamp_header: None,
+        }
+    }
+
+    fn sign(signature: &Signature, message: &mut Message) -> String {
+        signature.sign(message).unwrap();
+        message
+            .header
+            .iter()
+            .rfind(|(k, _)| *k == signature.header)
+            .map(|(_, v)| v.clone())
+            .unwrap()
+    }
+
+    fn message<'a>(url: &'a str, body: &'a [u8]) -> Message<'a> {
+        Message {
+            method: "POST",
+            url,
+            header: vec![("Content-Type".into(), "application/json".into())],
+            payload: Payload::Bytes(body),
+        }
+    }
+
+    #[test]
+    fn render_template() {
+        let f = |name: &str| Ok(format!("<{}>", name));
+        assert_eq!(render("a{{b}}c", f).unwrap(), "a{b}c");
+        assert_eq!(render("{ method }{path}", f).unwrap(), "<method><path>");
+        assert_eq!(render("x}", f).unwrap_err(), "unmatched } in template");
+        assert_eq!(render("{x", f).unwrap_err(), "unclosed { in template");
+    }
+
+    #[test]
+    fn validate() {
+        let valid = signature(
+            "{method}{header.X-Id}{{x}}",
+            Algorithm::Sha256,
+            Encoding::Hex,
+        );
+        assert!(valid.validate().is_ok());
+        assert_eq!(
+            signature("{method}{nope}", Algorithm::Sha256, Encoding::Hex).validate(),
+            Err("unknown placeholder {nope}".to_string())
+        );
+    }
+
+    // RFC 4231 and RFC 2202 test case 2
+    #[test]
+    fn algorithms() {
+        let data = "what do ya want for nothing?";
+        let mut m = message("http://h/", b"");
+        assert_eq!(
+            sign(&signature(data, Algorithm::Sha256, Encoding::Hex), &mut m),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            sign(&signature(data, Algorithm::Sha512, Encoding::Hex), &mut m),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75\
+             c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+        assert_eq!(
+            sign(&signature(data, Algorithm::Sha1, Encoding::Hex), &mut m),
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        );
+        assert_eq!(
+            sign(&signature(data, Algorithm::Sha1, Encoding::Base64), &mut m),
+            "7/zfauXrL6LSdBbV8YTfnCWafHk="
+        );
+    }
+
+    // expected value from Python's hmac module
+    #[test]
+    fn placeholders() {
+        let mut signature = signature(
+            "{method}\n{path}\n{query}\n{header.content-type}\n{body}",
+            Algorithm::Sha256,
+            Encoding::Hex,
+        );
+        signature.secret = "k".into();
+        let mut m = message("http://h:80/p?q=1#f", br#"{"a":1}"#);
+        assert_eq!(
+            sign(&signature, &mut m),
+            "0b16a699626b9165bb7767fddbc49eb26d8e82abef401fd1904f855a16098a89"
+        );
+    }
+
+    #[test]
+    fn timestamp_header() {
+        let mut signature = signature("{timestamp}", Algorithm::Sha256, Encoding::Hex);
+        signature.timestamp_header = Some("X-Timestamp".into());
+        let mut m = message("http://h/", b"");
+        let value = sign(&signature, &mut m);
+
+        let (_, timestamp) = &m.header[1];
+        assert_eq!(m.header[1].0, "X-Timestamp");
+        assert!(timestamp.parse::<u64>().is_ok());
+        let expected = hex::encode(mac::<Hmac<Sha256>>(b"Jefe", timestamp.as_bytes()));
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn multipart() {
+        let signature = signature("{body}", Algorithm::Sha256, Encoding::Hex);
+        let mut m = message("http://h/", b"");
+        m.payload = Payload::Multipart;
+        assert_eq!(
+            signature.sign(&mut m).unwrap_err().to_string(),
+            "a multipart body can't be signed"
+        );
+    }
+}
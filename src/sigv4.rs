@@ -0,0 +1,246 @@
+use crate::multimap::{deserialize_optional_scalar, deserialize_scalar};
+use crate::request::{split_url, Message, Payload, UNRESERVED};
+use crate::sign;
+use crate::variable;
+use hmac::Hmac;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, error::Error, fs::File, io};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Credentials for AWS Signature Version 4, from an `auth:` section with `type: aws_sigv4`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    #[serde(deserialize_with = "deserialize_scalar")]
+    pub access_key: String,
+    #[serde(deserialize_with = "deserialize_scalar")]
+    pub secret_key: String,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub session_token: Option<String>,
+    pub region: String,
+    pub service: String,
+}
+
+impl Credentials {
+    /// Adds the `x-amz-*` and `Authorization` headers to the message.
+    pub fn sign(&self, message: &mut Message) -> Result<(), Box<dyn Error>> {
+        self.sign_at(message, variable::timestamp())
+    }
+
+    /// Signs the message as if it were sent at `timestamp`, in seconds since the epoch.
+    fn sign_at(&self, message: &mut Message, timestamp: u64) -> Result<(), Box<dyn Error>> {
+        let date_time = variable::iso_date(timestamp).replace(&['-', ':'][..], "");
+        let date = &date_time[..8];
+        let (host, path, query) = split_url(message.url);
+
+        let payload_hash = match &message.payload {
+            Payload::Bytes(bytes) => hex::encode(Sha256::digest(bytes)),
+            Payload::File(path) => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut File::open(path)?, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            Payload::Multipart => "UNSIGNED-PAYLOAD".to_string(),
+        };
+
+        message
+            .header
+            .push(("x-amz-date".into(), date_time.clone()));
+        message
+            .header
+            .push(("x-amz-content-sha256".into(), payload_hash.clone()));
+        if let Some(token) = &self.session_token {
+            message
+                .header
+                .push(("x-amz-security-token".into(), token.clone()));
+        }
+
+        let mut header: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (k, v) in &message.header {
+            header
+                .entry(k.to_lowercase())
+                .or_default()
+                .push(v.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        header
+            .entry("host".into())
+            .or_insert_with(|| vec![host.to_string()]);
+
+        let signed_headers = header.keys().cloned().collect::<Vec<_>>().join(";");
+        let canonical_headers: String = header
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v.join(",")))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            message.method,
+            self.canonical_path(path),
+            canonical_query(query),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            date_time,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = hmac(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        let key = hmac(&key, self.region.as_bytes());
+        let key = hmac(&key, self.service.as_bytes());
+        let key = hmac(&key, b"aws4_request");
+        let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        message.header.push((
+            "Authorization".into(),
+            format!(
+                "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                ALGORITHM, self.access_key, scope, signed_headers, signature
+            ),
+        ));
+        Ok(())
+    }
+
+    /// Encodes each path segment once for S3 and twice for other services.
+    fn canonical_path(&self, path: &str) -> String {
+        path.split('/')
+            .map(|segment| {
+                let segment = encode(&percent_decode_str(segment).decode_utf8_lossy());
+                if self.service == "s3" {
+                    segment
+                } else {
+                    encode(&segment)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn canonical_query(query: &str) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|pair| {
+            let mut it = pair.splitn(2, '=');
+            let key = it.next().unwrap_or_default();
+            let value = it.next().unwrap_or_default();
+            (
+                encode(&percent_decode_str(key).decode_utf8_lossy()),
+                encode(&percent_decode_str(value).decode_utf8_lossy()),
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, UNRESERVED).to_string()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    sign::mac::<Hmac<Sha256>>(key, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2015-08-30T12:36:00Z, the date used in the AWS Signature Version 4 examples.
+    const TIMESTAMP: u64 = 1_440_938_160;
+
+    fn credentials(service: &str, session_token: Option<&str>) -> Credentials {
+        Credentials {
+            access_key: "AKIDEXAMPLE".into(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: session_token.map(|x| x.to_string()),
+            region: "us-east-1".into(),
+            service: service.into(),
+        }
+    }
+
+    fn authorization<'a>(message: &'a Message) -> &'a str {
+        message
+            .header
+            .iter()
+            .find(|(k, _)| k == "Authorization")
+            .map(|(_, v)| v.as_str())
+            .unwrap()
+    }
+
+    #[test]
+    fn canonical_path() {
+        let service = credentials("service", None);
+        let s3 = credentials("s3", None);
+        assert_eq!(service.canonical_path("/a%20b/c"), "/a%2520b/c");
+        assert_eq!(service.canonical_path("/a b/~x"), "/a%2520b/~x");
+        assert_eq!(s3.canonical_path("/a%20b/c"), "/a%20b/c");
+        assert_eq!(s3.canonical_path("/a b/c+d"), "/a%20b/c%2Bd");
+    }
+
+    #[test]
+    fn canonical_queries() {
+        assert_eq!(canonical_query(""), "");
+        assert_eq!(canonical_query("b=2&a=1&a=0&c"), "a=0&a=1&b=2&c=");
+        assert_eq!(canonical_query("k=x%2Fy&s=a b&t=~"), "k=x%2Fy&s=a%20b&t=~");
+    }
+
+    // The expected values were computed by botocore's SigV4Auth and S3SigV4Auth for the
+    // same requests, with the clock fixed at TIMESTAMP.
+    #[test]
+    fn sign_post() {
+        let mut message = Message {
+            method: "POST",
+            url: "https://example.amazonaws.com/a%20b/c?b=2&a=1&c=x%2Fy",
+            header: vec![("Content-Type".into(), "application/json".into())],
+            payload: Payload::Bytes(b"hello"),
+        };
+        credentials("service", None)
+            .sign_at(&mut message, TIMESTAMP)
+            .unwrap();
+        assert_eq!(
+            authorization(&message),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date, \
+             Signature=6f2f17275e24972fc65d0f1912e7e03bc9690aec69d3f7efdc6d2eea73b9b472"
+        );
+    }
+
+    #[test]
+    fn sign_s3_with_session_token() {
+        let mut message = Message {
+            method: "GET",
+            url: "https://bucket.s3.amazonaws.com/dir/a%20b.txt",
+            header: vec![],
+            payload: Payload::Bytes(b""),
+        };
+        credentials("s3", Some("TOKEN"))
+            .sign_at(&mut message, TIMESTAMP)
+            .unwrap();
+        assert_eq!(
+            authorization(&message),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token, \
+             Signature=e53d5aee5c1a355654ff9362ad15e54659ae954276582f5a470228c341397955"
+        );
+    }
+}
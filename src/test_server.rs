@@ -0,0 +1,83 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A local HTTP server for tests. Each connection gets the next response in order and is
+/// then closed. The requests it receives are recorded with their bodies.
+pub(crate) struct Server {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+    /// `responses` are status lines and headers, followed by an optional body after a blank line.
+    pub fn start(responses: Vec<&str>) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses: Vec<String> = responses.into_iter().map(response).collect();
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = match listener.accept() {
+                    Ok(x) => x,
+                    Err(_) => return,
+                };
+                let request = read_request(&mut BufReader::new(&stream));
+                recorded.lock().unwrap().push(request);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        Server { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn response(s: &str) -> String {
+    let (head, body) = match s.find("\n\n") {
+        Some(i) => (&s[..i], &s[i + 2..]),
+        None => (s, ""),
+    };
+    let mut out = String::new();
+    for line in head.lines() {
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    out
+}
+
+fn read_request(reader: &mut impl BufRead) -> String {
+    let mut request = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return request;
+        }
+        let lower = line.to_ascii_lowercase();
+        if let Some(value) = lower.strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap_or(0);
+        }
+        request.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    let mut body = vec![0; length];
+    if reader.read_exact(&mut body).is_ok() {
+        request.push_str(&String::from_utf8_lossy(&body));
+    }
+    request
+}
@@ -0,0 +1,363 @@
+use crate::Group;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Variables captured from responses, kept per group for the lifetime of the process.
+static VARIABLES: Mutex<Option<HashMap<String, HashMap<String, String>>>> = Mutex::new(None);
+
+pub fn set(group_id: &str, name: String, value: String) {
+    let mut vars = VARIABLES.lock().unwrap();
+    vars.get_or_insert_with(HashMap::new)
+        .entry(group_id.to_string())
+        .or_default()
+        .insert(name, value);
+}
+
+pub fn get_all(group_id: &str) -> HashMap<String, String> {
+    let vars = VARIABLES.lock().unwrap();
+    vars.as_ref()
+        .and_then(|x| x.get(group_id))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Substitutes variable references in request text and values. Names are looked up in the
+/// captured variables, the group `env`, the group dotenv file and the built-ins, in that order.
+pub struct Substitution {
+    vars: HashMap<String, String>,
+    missing: Vec<String>,
+}
+
+impl Substitution {
+    pub fn new(group: Option<&Group>) -> io::Result<Self> {
+        let mut vars = get_all(group.map(|x| x.id.as_str()).unwrap_or(""));
+        if let Some(group) = group {
+            if let Some(env) = &group.env {
+                for (k, v) in env {
+                    vars.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+            for (k, v) in group.load_dotenv()? {
+                vars.entry(k).or_insert(v);
+            }
+        }
+
+        Ok(Substitution {
+            vars,
+            missing: Vec::new(),
+        })
+    }
+
+    /// Names referenced so far that could not be resolved.
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Replaces variable references in text. `${name:-default}` falls back to `default` and
+    /// `$$` is a literal dollar sign. Unresolved references are left as they are.
+    pub fn text(&mut self, s: &str) -> String {
+        let mut v = String::with_capacity(s.len());
+        let mut rest = s;
+
+        while let Some(pos) = rest.find('$') {
+            v.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+
+            if rest.starts_with("$$") {
+                v.push('$');
+                rest = &rest[2..];
+                continue;
+            }
+
+            match variable_at(rest) {
+                Some((expr, len)) => {
+                    match self.resolve(expr) {
+                        Some(value) => v.push_str(&value),
+                        None => v.push_str(&rest[..len]),
+                    }
+                    rest = &rest[len..];
+                }
+                None => {
+                    v.push('$');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        v.push_str(rest);
+        v
+    }
+
+    /// Replaces variable references in every string of a YAML value, including keys. A string
+    /// that consists of a single reference becomes a boolean or number when the variable's
+    /// value is one, so that typed fields such as `timeout: $t` can take variables.
+    pub fn yaml(&mut self, value: serde_yaml::Value) -> serde_yaml::Value {
+        use serde_yaml::Value;
+
+        match value {
+            Value::String(s) => match variable_at(&s) {
+                Some((expr, len)) if len == s.len() => match self.resolve(expr) {
+                    Some(value) => yaml_scalar(&value).unwrap_or(Value::String(value)),
+                    None => Value::String(s),
+                },
+                _ => Value::String(self.text(&s)),
+            },
+            Value::Sequence(v) => Value::Sequence(v.into_iter().map(|x| self.yaml(x)).collect()),
+            Value::Mapping(m) => Value::Mapping(
+                m.into_iter()
+                    .map(|(k, v)| (self.yaml(k), self.yaml(v)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Replaces variable references in a JSON value. A string that consists of a single
+    /// reference takes the type of the variable's value when it parses as JSON, so numbers,
+    /// booleans and objects are inserted as such.
+    pub fn json(&mut self, value: serde_json::Value) -> serde_json::Value {
+        use serde_json::Value;
+
+        match value {
+            Value::String(s) => match variable_at(&s) {
+                Some((expr, len)) if len == s.len() => match self.resolve(expr) {
+                    Some(value) => serde_json::from_str(&value).unwrap_or(Value::String(value)),
+                    None => Value::String(s),
+                },
+                _ => Value::String(self.text(&s)),
+            },
+            Value::Array(v) => Value::Array(v.into_iter().map(|x| self.json(x)).collect()),
+            Value::Object(m) => Value::Object(
+                m.into_iter()
+                    .map(|(k, v)| (self.text(&k), self.json(v)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    fn resolve(&mut self, expr: &str) -> Option<String> {
+        let (name, default) = match expr.find(":-") {
+            Some(i) => (&expr[..i], Some(&expr[i + 2..])),
+            None => (expr, None),
+        };
+
+        let value = if let Some(name) = name.strip_prefix("env:") {
+            std::env::var(name).ok()
+        } else {
+            self.vars.get(name).cloned().or_else(|| builtin(name))
+        };
+
+        let value = value.or_else(|| default.map(|x| x.to_string()));
+        if value.is_none() && !self.missing.iter().any(|x| x == name) {
+            self.missing.push(name.to_string());
+        }
+        value
+    }
+}
+
+/// Parses a boolean or number, if YAML prints it back as the same text. Values such as
+/// `007` are left as strings, so that string fields get them unchanged.
+fn yaml_scalar(s: &str) -> Option<serde_yaml::Value> {
+    use serde_yaml::Value;
+
+    match serde_yaml::from_str(s).ok()? {
+        value @ Value::Bool(_) | value @ Value::Number(_) => {
+            let text = serde_yaml::to_string(&value).ok()?;
+            if text.trim_start_matches("---").trim() == s {
+                Some(value)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Finds the variable reference (`$name` or `${expr}`) at the start of `s`, returning the
+/// expression and the length of the reference.
+fn variable_at(s: &str) -> Option<(&str, usize)> {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&b'$') {
+        return None;
+    }
+
+    if bytes.get(1) == Some(&b'{') {
+        let end = s.find(&['}', '\n'][..])?;
+        if bytes[end] != b'}' || end == 2 {
+            return None;
+        }
+        Some((&s[2..end], end + 1))
+    } else {
+        let len = bytes[1..]
+            .iter()
+            .position(|&b| !(is_alpha_digit(b) || b == b'_'))
+            .map(|x| x + 1)
+            .unwrap_or_else(|| bytes.len());
+        if len == 1 || bytes[1] == b'_' {
+            return None;
+        }
+        Some((&s[1..len], len))
+    }
+}
+
+#[inline]
+fn is_alpha_digit(b: u8) -> bool {
+    b.is_ascii_alphanumeric()
+}
+
+/// Resolves the built-in dynamic variables. Each call produces a fresh value.
+pub fn builtin(name: &str) -> Option<String> {
+    match name {
+        "uuid" => Some(uuid()),
+        "timestamp" => Some(timestamp().to_string()),
+        "isoDate" => Some(iso_date(timestamp())),
+        "randomInt" => Some(rand::thread_rng().gen_range(0, 1000).to_string()),
+        "randomString" => Some(
+            rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(16)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+pub(crate) fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+fn uuid() -> String {
+    let mut b: [u8; 16] = rand::random();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    format!(
+        "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+        b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]
+    )
+}
+
+/// Formats a unix timestamp as an ISO 8601 UTC date time.
+pub(crate) fn iso_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitution(env: &[(&str, &str)]) -> Substitution {
+        let group = Group {
+            env: Some(
+                env.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        Substitution::new(Some(&group)).unwrap()
+    }
+
+    #[test]
+    fn text() {
+        let mut sub = substitution(&[("host", "example.com"), ("id", "7")]);
+        assert_eq!(
+            sub.text("http://$host/users/${id}?x=${y:-1}&p=$$5&z=$"),
+            "http://example.com/users/7?x=1&p=$5&z=$"
+        );
+        assert_eq!(sub.text("${missing}/$other"), "${missing}/$other");
+        assert_eq!(sub.missing(), ["missing", "other"]);
+    }
+
+    #[test]
+    fn json() {
+        let mut sub = substitution(&[("n", "5"), ("flag", "true"), ("name", "a\"b")]);
+        let value = serde_json::json!({
+            "n": "$n",
+            "flag": "${flag}",
+            "name": "$name",
+            "text": "n=$n",
+            "list": ["$n", "${nope}"],
+        });
+        assert_eq!(
+            sub.json(value),
+            serde_json::json!({
+                "n": 5,
+                "flag": true,
+                "name": "a\"b",
+                "text": "n=5",
+                "list": [5, "${nope}"],
+            })
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        let mut sub = substitution(&[("n", "30"), ("f", "1.5"), ("b", "true"), ("z", "007")]);
+        let value: serde_yaml::Value =
+            serde_yaml::from_str("[$n, '${f}', $b, $z, n=$n, $nope]").unwrap();
+        let expected: serde_yaml::Value =
+            serde_yaml::from_str("[30, 1.5, true, '007', n=30, $nope]").unwrap();
+        assert_eq!(sub.yaml(value), expected);
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(iso_date(0), "1970-01-01T00:00:00Z");
+        assert_eq!(iso_date(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(iso_date(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(iso_date(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn uuid_bits() {
+        let id = uuid();
+        let parts: Vec<&str> = id.split('-').collect();
+        let lens: Vec<usize> = parts.iter().map(|x| x.len()).collect();
+        assert_eq!(lens, [8, 4, 4, 4, 12]);
+        assert!(id.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+        assert!(parts[2].starts_with('4'));
+        assert!(parts[3].starts_with(|c| "89ab".contains(c)));
+    }
+
+    #[test]
+    fn builtins() {
+        let s = builtin("randomString").unwrap();
+        assert_eq!(s.len(), 16);
+        assert!(s.bytes().all(is_alpha_digit));
+        let n: u32 = builtin("randomInt").unwrap().parse().unwrap();
+        assert!(n < 1000);
+        assert!(builtin("isoDate").unwrap().ends_with('Z'));
+        assert_eq!(builtin("nope"), None);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{config, db, CssDialog, Editor, Group, GroupDialog, Request, RequestStore, Response};
+use crate::{
+    config, db, pretty, AssertResult, CookieDialog, CssDialog, Editor, Group, GroupDialog,
+    Request, RequestStore, Response,
+};
 use gdk::EventButton;
 use gio::prelude::*;
 use gio::SimpleAction;
@@ -6,11 +9,10 @@ use glade_macro::ui;
 use glib::{BindingFlags, MainContext, Sender, SignalHandlerId};
 use gtk::{
     prelude::*, ApplicationWindow, Button, CellRendererTextBuilder, ComboBoxText, CssProvider,
-    Entry, InfoBar, Label, Menu, MenuItem, Popover, ResponseType, Revealer, SearchBar, SearchEntry,
-    Spinner, StyleContext, TextView, ToggleButton, TreeIter, TreeView, TreeViewColumn,
+    Entry, InfoBar, Label, Menu, MenuItem, MessageType, Popover, ResponseType, Revealer, SearchBar,
+    SearchEntry, Spinner, StyleContext, TextView, ToggleButton, TreeIter, TreeView, TreeViewColumn,
 };
-use serde_json::Value;
-use std::{cell::RefCell, ops::Deref, ops::DerefMut, rc::Rc};
+use std::{cell::RefCell, collections::VecDeque, ops::Deref, ops::DerefMut, rc::Rc};
 
 macro_rules! action {
     ($window:expr, $name:expr, $slot:block) => {{
@@ -30,6 +32,7 @@ struct UI {
     request: sourceview::View,
     response: sourceview::View,
     run: Button,
+    run_all: Button,
     spinner: Spinner,
     cancel: Button,
     revealer: Revealer,
@@ -37,6 +40,9 @@ struct UI {
     error: Label,
     status: Label,
     time: Label,
+    assertion: Label,
+    assertion_popover: Popover,
+    assertion_list: Label,
     header_popover: Popover,
     header: TextView,
     tree: TreeView,
@@ -54,6 +60,7 @@ struct State {
     group: Option<Vec<Group>>,
     group_id: Option<String>,
     request: Option<Request>,
+    queue: VecDeque<String>,
     header: Option<String>,
     sender: Option<Sender<Msg>>,
     store: RefCell<RequestStore>,
@@ -131,7 +138,12 @@ impl Window {
 
         let this = self.clone();
         self.ui.run.connect_clicked(move |_| {
-            this.on_request();
+            this.on_request(false);
+        });
+
+        let this = self.clone();
+        self.ui.run_all.connect_clicked(move |_| {
+            this.on_request(true);
         });
 
         let this = self.clone();
@@ -152,6 +164,12 @@ impl Window {
             Inhibit(true)
         });
 
+        let this = self.clone();
+        self.ui.assertion.connect_activate_link(move |_, _| {
+            this.ui.assertion_popover.show();
+            Inhibit(true)
+        });
+
         let this = self.clone();
         let id = self.ui.group.connect_changed(move |_| {
             this.state.borrow_mut().group_id = this.ui.group.get_active_id().map(|x| x.to_string());
@@ -239,6 +257,16 @@ impl Window {
         action!(self, "font", {
             this.handle_font_action();
         });
+
+        let this = self.clone();
+        action!(self, "cookie", {
+            this.handle_cookie_action();
+        });
+
+        let this = self.clone();
+        action!(self, "clear_cookie", {
+            this.handle_clear_cookie_action();
+        });
     }
 
     fn handle_group_action(&self) {
@@ -261,6 +289,20 @@ impl Window {
         }
     }
 
+    fn handle_cookie_action(&self) {
+        let group_id = self.state.borrow().group_id.clone().unwrap_or_default();
+        let dlg = CookieDialog::new(group_id);
+        dlg.run();
+        dlg.hide();
+    }
+
+    fn handle_clear_cookie_action(&self) {
+        let group_id = self.state.borrow().group_id.clone().unwrap_or_default();
+        if let Err(e) = config::clear_cookie(&group_id) {
+            self.show_error(e.to_string());
+        }
+    }
+
     fn setup_editor(&self) {
         self.ui.request.set_language("yaml");
         self.ui.request.set_theme("kate");
@@ -306,26 +348,43 @@ impl Window {
     }
 
     fn show_error(&self, error: impl AsRef<str>) {
+        self.ui.info_bar.set_message_type(MessageType::Error);
         self.ui.error.set_text(error.as_ref());
         self.ui.revealer.set_reveal_child(true);
     }
 
+    fn show_warning(&self, warning: impl AsRef<str>) {
+        self.ui.info_bar.set_message_type(MessageType::Warning);
+        self.ui.error.set_text(warning.as_ref());
+        self.ui.revealer.set_reveal_child(true);
+    }
+
     fn pre_request(&self, request: Request) {
+        let warnings = request.warnings();
         self.state.borrow_mut().request = Some(request);
 
-        self.ui.revealer.set_reveal_child(false);
+        if warnings.is_empty() {
+            self.ui.revealer.set_reveal_child(false);
+        } else {
+            self.show_warning(format!("警告：{}", warnings.join("\n")));
+        }
         self.ui.run.set_visible(false);
+        self.ui.run_all.set_visible(false);
         self.ui.cancel.set_visible(true);
         self.ui.spinner.set_visible(true);
         self.ui.status.set_visible(false);
         self.ui.time.set_visible(false);
+        self.ui.assertion.set_visible(false);
         self.ui.response.set_text("");
     }
 
     fn render_response(&self, s: &str) {
-        if let Some(json) = json_pretty(s) {
+        if let Some(json) = pretty::json(s) {
             self.ui.response.set_language("json");
             self.ui.response.set_text(&json);
+        } else if let Some((language, text)) = pretty::markup(s) {
+            self.ui.response.set_language(language);
+            self.ui.response.set_text(&text);
         } else {
             self.ui.response.set_language("");
             self.ui.response.set_text(s);
@@ -333,17 +392,30 @@ impl Window {
     }
 
     fn post_request(&self, response: Option<Response>) {
+        let done = response.is_some();
+        let mut failed = false;
+
         self.ui.run.set_visible(true);
+        self.ui.run_all.set_visible(true);
         self.ui.cancel.set_visible(false);
         self.ui.spinner.set_visible(false);
 
         if let Some(response) = response {
+            let mut results = Vec::new();
+            let mut missing = Vec::new();
+            let mut errors = Vec::new();
             if let Some(request) = &self.state.borrow().request {
+                results = request.check(&response);
+                missing = request.capture(&response);
+                errors = request.graphql_errors(&response);
                 let state = self.state.borrow();
                 let mut store = state.store.borrow_mut();
                 match store.put(request, &response) {
                     Ok(..) => (),
-                    Err(e) => self.show_error(e.to_string()),
+                    Err(e) => {
+                        failed = true;
+                        self.show_error(e.to_string());
+                    }
                 };
                 //store.select_set_top(&request.method, &request.url, &self.ui.tree);
             }
@@ -357,19 +429,83 @@ impl Window {
             }
             self.ui.time.set_visible(true);
             self.ui.time.set_text(&format!("{} ms", response.time));
+            self.show_assertion(&results);
+            failed |= results.iter().any(|x| !x.pass) || !errors.is_empty() || !missing.is_empty();
+            if !errors.is_empty() {
+                self.show_error(errors.join("\n"));
+            } else if !missing.is_empty() {
+                self.show_error(format!("未能提取变量：{}", missing.join(", ")));
+            }
 
             self.render_response(&response.body);
         }
 
         self.state.borrow_mut().request = None;
+
+        // stop running the queue at the first failure, so that its result stays visible
+        if done && !failed {
+            self.next_request();
+        } else {
+            self.state.borrow_mut().queue.clear();
+        }
+    }
+
+    fn show_assertion(&self, results: &[AssertResult]) {
+        if results.is_empty() {
+            return;
+        }
+
+        let passed = results.iter().filter(|x| x.pass).count();
+        let mark = if passed == results.len() { "✔" } else { "✘" };
+        self.ui.assertion.set_markup(&format!(
+            "<a href='#'>{} {}/{}</a>",
+            mark,
+            passed,
+            results.len()
+        ));
+
+        let list: Vec<String> = results
+            .iter()
+            .map(|x| {
+                let (color, mark) = if x.pass { ("green", "✔") } else { ("red", "✘") };
+                format!(
+                    "<span foreground='{}'>{}</span> {}",
+                    color,
+                    mark,
+                    glib::markup_escape_text(&x.message)
+                )
+            })
+            .collect();
+        self.ui.assertion_list.set_markup(&list.join("\n"));
+        self.ui.assertion.set_visible(true);
     }
 
-    fn on_request(&self) {
+    fn on_request(&self, all: bool) {
         let text = String::from(self.ui.request.text().unwrap());
         if text.as_str().trim().is_empty() {
             return;
         }
 
+        let documents = if all {
+            Request::documents(&text)
+                .into_iter()
+                .map(|x| x.to_string())
+                .collect()
+        } else {
+            let line = self.ui.request.cursor_line().unwrap_or(0);
+            vec![Request::document_at(&text, line).to_string()]
+        };
+
+        self.state.borrow_mut().queue = documents.into();
+        self.next_request();
+    }
+
+    fn next_request(&self) {
+        let text = match self.state.borrow_mut().queue.pop_front() {
+            Some(text) => text,
+            None => return,
+        };
+
         match Request::parse(text, self.group()) {
             Ok(req) => {
                 self.pre_request(req.clone());
@@ -380,7 +516,10 @@ impl Window {
                         .expect("send failed");
                 });
             }
-            Err(e) => self.show_error(e.to_string()),
+            Err(e) => {
+                self.state.borrow_mut().queue.clear();
+                self.show_error(e.to_string());
+            }
         };
     }
 
@@ -482,6 +621,7 @@ impl Window {
         self.render_response(&res);
         self.ui.status.hide();
         self.ui.time.hide();
+        self.ui.assertion.hide();
         Some(())
     }
 
@@ -538,11 +678,6 @@ fn option_eq<T: PartialEq<U>, U: ?Sized>(x: &Option<T>, y: &U) -> bool {
     }
 }
 
-fn json_pretty(s: &str) -> Option<String> {
-    let json: Value = serde_json::from_str(s).ok()?;
-    serde_json::to_string_pretty(&json).ok()
-}
-
 enum MenuAction {
     Delete,
     Rename,